@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{ Deserialize, Serialize };
+use utoipa::ToSchema;
+
+use crate::config::settings::SettingsError;
+
+/// Optional JSON file overriding the header aliases below.
+pub const SHEET_COLUMNS_FILE_ENV: &str = "SHEET_COLUMNS_FILE";
+
+// Logical sheet fields. Sheets are matched to these by header text, never by position.
+pub const MARKETPLACE: &str = "MARKETPLACE";
+pub const BIN_RACK: &str = "BIN_RACK";
+pub const ORDER_ID: &str = "ORDER_ID";
+pub const RETURN_REASON: &str = "RETURN_REASON";
+pub const REFUND_YES: &str = "REFUND_YES";
+pub const DATE: &str = "DATE";
+pub const REFUNDED: &str = "REFUNDED";
+pub const STOCK_ADDED: &str = "STOCK_ADDED";
+pub const REFUND_DATE: &str = "REFUND_DATE";
+pub const MATCH_TYPE: &str = "MATCH_TYPE";
+pub const FRASER_CLASSIFICATION: &str = "FRASER_CLASSIFICATION";
+pub const RETURN_ORDER: &str = "RETURN_ORDER";
+pub const SHOPIFY_ID: &str = "SHOPIFY_ID";
+pub const RETURNED_SKU: &str = "RETURNED_SKU";
+pub const OFFER_SKU: &str = "OFFER_SKU";
+pub const MATCHED_SKU: &str = "MATCHED_SKU";
+pub const ROW_NUMBER: &str = "ROW_NUMBER";
+pub const MANUAL_CONFIRMATION: &str = "MANUAL_CONFIRMATION";
+pub const STATUS: &str = "STATUS";
+pub const QTY: &str = "QTY";
+pub const MAIN_UPDATED: &str = "MAIN_UPDATED";
+
+/// A logical field and the header texts that identify its column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnSpec {
+    pub field: &'static str,
+    pub aliases: Vec<String>,
+    pub required: bool,
+}
+
+impl ColumnSpec {
+    fn new(field: &'static str, aliases: &[&str], required: bool) -> Self {
+        Self {
+            field,
+            aliases: aliases
+                .iter()
+                .map(|a| a.to_string())
+                .collect(),
+            required,
+        }
+    }
+}
+
+/// Header alias table for both tabs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnAliases {
+    pub sheet1: Vec<ColumnSpec>,
+    pub sheet2: Vec<ColumnSpec>,
+}
+
+impl Default for ColumnAliases {
+    fn default() -> Self {
+        // The misspelled headers ("MARTKETPLACE", "RETURN_REAS", ...) are what the live sheets use.
+        Self {
+            sheet1: vec![
+                ColumnSpec::new(MARKETPLACE, &[MARKETPLACE, "MARTKETPLACE"], true),
+                ColumnSpec::new(BIN_RACK, &[BIN_RACK], false),
+                ColumnSpec::new(ORDER_ID, &[ORDER_ID], true),
+                ColumnSpec::new(RETURN_REASON, &[RETURN_REASON, "RETURN_REAS"], false),
+                ColumnSpec::new(REFUND_YES, &[REFUND_YES], false),
+                ColumnSpec::new(DATE, &[DATE], false),
+                ColumnSpec::new(REFUNDED, &[REFUNDED], false),
+                ColumnSpec::new(STOCK_ADDED, &[STOCK_ADDED], false),
+                ColumnSpec::new(REFUND_DATE, &[REFUND_DATE], false),
+                ColumnSpec::new(MATCH_TYPE, &[MATCH_TYPE], false),
+                ColumnSpec::new(FRASER_CLASSIFICATION, &[FRASER_CLASSIFICATION], false)
+            ],
+            sheet2: vec![
+                ColumnSpec::new(RETURN_ORDER, &[RETURN_ORDER], true),
+                ColumnSpec::new(SHOPIFY_ID, &[SHOPIFY_ID], false),
+                ColumnSpec::new(RETURNED_SKU, &[RETURNED_SKU], false),
+                ColumnSpec::new(OFFER_SKU, &[OFFER_SKU], false),
+                ColumnSpec::new(MATCHED_SKU, &[MATCHED_SKU], false),
+                ColumnSpec::new(MATCH_TYPE, &[MATCH_TYPE], false),
+                ColumnSpec::new(ROW_NUMBER, &[ROW_NUMBER], false),
+                ColumnSpec::new(MANUAL_CONFIRMATION, &[MANUAL_CONFIRMATION, "MANUAL_CONFIRMAT"], false),
+                ColumnSpec::new(STATUS, &[STATUS], false),
+                ColumnSpec::new(MARKETPLACE, &[MARKETPLACE], false),
+                ColumnSpec::new(QTY, &[QTY, "QUANTITY"], false),
+                ColumnSpec::new(MAIN_UPDATED, &[MAIN_UPDATED], false)
+            ],
+        }
+    }
+}
+
+/// Alias overrides per tab, logical field -> replacement aliases. Used for the
+/// `SHEET_COLUMNS_FILE` and for each sync target's column mapping.
+///
+/// ```json
+/// { "sheet1": { "ORDER_ID": ["Order #", "Order Number"] } }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({ "sheet1": { "ORDER_ID": ["Order #", "Order Number"] } }))]
+pub struct ColumnOverrides {
+    #[serde(default)]
+    pub sheet1: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub sheet2: BTreeMap<String, Vec<String>>,
+}
+
+impl ColumnAliases {
+    /// Loads the default table with the aliases from a JSON override file applied.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, SettingsError> {
+        let path = path.as_ref();
+        let invalid = |reason: String| SettingsError::Invalid { key: SHEET_COLUMNS_FILE_ENV, reason };
+
+        let raw = std::fs
+            ::read_to_string(path)
+            .map_err(|e| invalid(format!("failed to read {:?}: {}", path, e)))?;
+        let overrides: ColumnOverrides = serde_json
+            ::from_str(&raw)
+            .map_err(|e| invalid(format!("failed to parse {:?}: {}", path, e)))?;
+
+        Self::default().with_overrides(&overrides).map_err(invalid)
+    }
+
+    /// This table with `overrides` applied. Fails on unknown fields and empty alias lists.
+    pub fn with_overrides(mut self, overrides: &ColumnOverrides) -> Result<Self, String> {
+        apply_overrides(&mut self.sheet1, &overrides.sheet1, "sheet1")?;
+        apply_overrides(&mut self.sheet2, &overrides.sheet2, "sheet2")?;
+        Ok(self)
+    }
+}
+
+fn apply_overrides(
+    specs: &mut [ColumnSpec],
+    overrides: &BTreeMap<String, Vec<String>>,
+    tab: &str
+) -> Result<(), String> {
+    for (field, aliases) in overrides {
+        let spec = specs
+            .iter_mut()
+            .find(|spec| spec.field.eq_ignore_ascii_case(field))
+            .ok_or_else(|| format!("unknown {} field {:?}", tab, field))?;
+        if aliases.is_empty() {
+            return Err(format!("{} field {} needs at least one alias", tab, spec.field));
+        }
+        spec.aliases = aliases.clone();
+    }
+    Ok(())
+}
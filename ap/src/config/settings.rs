@@ -0,0 +1,274 @@
+use std::path::{ Path, PathBuf };
+use std::time::Duration;
+
+use secrecy::SecretString;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::config::columns::{ ColumnAliases, SHEET_COLUMNS_FILE_ENV };
+
+/// Optional dotenv-style file to load settings from. Values already present in
+/// the process environment always win over the file.
+pub const CONFIG_FILE_ENV: &str = "APP_CONFIG_FILE";
+
+const SERVICE_ACCOUNT_FILE_ENV: &str = "GOOGLE_SERVICE_ACCOUNT_FILE";
+const TOKEN_URI_ENV: &str = "GOOGLE_TOKEN_URI";
+const SHEETS_API_URL_ENV: &str = "SHEETS_API_URL";
+const SPREADSHEET_ID_ENV: &str = "SHEETS_SPREADSHEET_ID";
+const SHEET1_SPREADSHEET_ID_ENV: &str = "SHEET1_SPREADSHEET_ID";
+const SHEET2_SPREADSHEET_ID_ENV: &str = "SHEET2_SPREADSHEET_ID";
+const SHEET1_NAME_ENV: &str = "SHEET1_NAME";
+const SHEET2_NAME_ENV: &str = "SHEET2_NAME";
+const IMPORT_DIR_ENV: &str = "IMPORT_DIR";
+const LMDB_PATH_ENV: &str = "LMDB_PATH";
+const BIND_ADDRESS_ENV: &str = "BIND_ADDRESS";
+const SYNC_INTERVAL_ENV: &str = "SYNC_INTERVAL_SECS";
+const SYNC_JITTER_ENV: &str = "SYNC_JITTER_SECS";
+const HTTP_TIMEOUT_ENV: &str = "SHEETS_HTTP_TIMEOUT_SECS";
+const REQUESTS_PER_MINUTE_ENV: &str = "SHEETS_REQUESTS_PER_MINUTE";
+const MAX_RETRIES_ENV: &str = "SHEETS_MAX_RETRIES";
+
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+const DEFAULT_SHEETS_API_URL: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("missing required setting `{0}`")]
+    Missing(&'static str),
+
+    #[error("invalid value for `{key}`: {reason}")]
+    Invalid {
+        key: &'static str,
+        reason: String,
+    },
+
+    #[error("failed to load config file {path:?}: {source}")]
+    ConfigFile {
+        path: PathBuf,
+        source: dotenvy::Error,
+    },
+
+    #[error("failed to read service account file {path:?}: {source}")]
+    ServiceAccountRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("invalid service account file {path:?}: {source}")]
+    ServiceAccountParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+/// The subset of a Google service-account JSON key the sheet sync needs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    pub private_key: SecretString,
+    #[serde(default = "default_token_uri")]
+    pub token_uri: String,
+}
+
+fn default_token_uri() -> String {
+    DEFAULT_TOKEN_URI.to_string()
+}
+
+impl ServiceAccountKey {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, SettingsError> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path).map_err(|source| SettingsError::ServiceAccountRead {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        serde_json::from_str(&raw).map_err(|source| SettingsError::ServiceAccountParse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// A single tab inside a spreadsheet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SheetLocation {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub service_account: ServiceAccountKey,
+    /// Base URL of the Sheets values API, without a trailing slash.
+    pub sheets_api_url: String,
+    pub sheet1: SheetLocation,
+    pub sheet2: SheetLocation,
+    /// Header aliases used to find each field's column in the tabs.
+    pub columns: ColumnAliases,
+    /// Directory of marketplace CSV/XLSX exports imported alongside Sheet1.
+    pub import_dir: Option<PathBuf>,
+    pub lmdb_path: PathBuf,
+    pub bind_address: String,
+    /// Pause between two background sheet syncs.
+    pub sync_interval: Duration,
+    /// Upper bound of the random delay added to each pause.
+    pub sync_jitter: Duration,
+    /// Limit for a whole Google API request, including reading the body.
+    pub http_timeout: Duration,
+    /// Sheets API calls allowed per rolling minute, kept under the per-user quota.
+    pub requests_per_minute: u32,
+    /// Retries after a 429, 5xx or network error before giving up.
+    pub max_retries: u32,
+}
+
+impl Settings {
+    /// Loads settings from the environment, after merging in the file named by
+    /// `APP_CONFIG_FILE` (or `.env` in the working directory when it exists).
+    pub fn load() -> Result<Self, SettingsError> {
+        match std::env::var_os(CONFIG_FILE_ENV) {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                dotenvy::from_path(&path).map_err(|source| SettingsError::ConfigFile {
+                    path,
+                    source,
+                })?;
+            }
+            None => {
+                // A missing .env is fine, a malformed one is not.
+                match dotenvy::dotenv() {
+                    Err(e) if !e.not_found() => {
+                        return Err(SettingsError::ConfigFile { path: PathBuf::from(".env"), source: e });
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Self::from_env()
+    }
+
+    /// Builds settings from the process environment only.
+    pub fn from_env() -> Result<Self, SettingsError> {
+        let service_account_path = PathBuf::from(required(SERVICE_ACCOUNT_FILE_ENV)?);
+        let mut service_account = ServiceAccountKey::from_file(&service_account_path)?;
+        if let Some(token_uri) = optional(TOKEN_URI_ENV) {
+            service_account.token_uri = token_uri;
+        }
+
+        let sheets_api_url = optional(SHEETS_API_URL_ENV)
+            .unwrap_or_else(|| DEFAULT_SHEETS_API_URL.to_string())
+            .trim_end_matches('/')
+            .to_string();
+
+        // Both tabs default to the shared spreadsheet, but either can be pointed elsewhere.
+        let shared_id = optional(SPREADSHEET_ID_ENV);
+        let sheet_id = |key: &'static str| {
+            optional(key)
+                .or_else(|| shared_id.clone())
+                .ok_or(SettingsError::Missing(SPREADSHEET_ID_ENV))
+        };
+
+        let sheet1 = SheetLocation {
+            spreadsheet_id: sheet_id(SHEET1_SPREADSHEET_ID_ENV)?,
+            sheet_name: optional(SHEET1_NAME_ENV).unwrap_or_else(|| "Sheet1".to_string()),
+        };
+        let sheet2 = SheetLocation {
+            spreadsheet_id: sheet_id(SHEET2_SPREADSHEET_ID_ENV)?,
+            sheet_name: optional(SHEET2_NAME_ENV).unwrap_or_else(|| "Sheet2".to_string()),
+        };
+
+        let columns = match optional(SHEET_COLUMNS_FILE_ENV) {
+            Some(path) => ColumnAliases::from_file(path)?,
+            None => ColumnAliases::default(),
+        };
+
+        let import_dir = optional(IMPORT_DIR_ENV).map(PathBuf::from);
+        if let Some(dir) = import_dir.as_ref().filter(|dir| !dir.is_dir()) {
+            return Err(SettingsError::Invalid {
+                key: IMPORT_DIR_ENV,
+                reason: format!("{:?} is not a directory", dir),
+            });
+        }
+
+        let lmdb_path = PathBuf::from(optional(LMDB_PATH_ENV).unwrap_or_else(|| "./data/lmdb".to_string()));
+
+        let bind_address = optional(BIND_ADDRESS_ENV).unwrap_or_else(|| "127.0.0.1:8080".to_string());
+        let has_port = bind_address
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+        if !has_port {
+            return Err(SettingsError::Invalid {
+                key: BIND_ADDRESS_ENV,
+                reason: format!("expected host:port, got {:?}", bind_address),
+            });
+        }
+
+        let sync_interval = Duration::from_secs(seconds(SYNC_INTERVAL_ENV, 300)?);
+        if sync_interval.is_zero() {
+            return Err(SettingsError::Invalid {
+                key: SYNC_INTERVAL_ENV,
+                reason: "must be greater than zero".to_string(),
+            });
+        }
+        let sync_jitter = Duration::from_secs(seconds(SYNC_JITTER_ENV, 30)?);
+
+        let http_timeout = Duration::from_secs(seconds(HTTP_TIMEOUT_ENV, 30)?);
+        // Google allows 60 read requests per minute per user by default
+        let requests_per_minute = number(REQUESTS_PER_MINUTE_ENV, 60)?;
+        if http_timeout.is_zero() || requests_per_minute == 0 {
+            return Err(SettingsError::Invalid {
+                key: if http_timeout.is_zero() { HTTP_TIMEOUT_ENV } else { REQUESTS_PER_MINUTE_ENV },
+                reason: "must be greater than zero".to_string(),
+            });
+        }
+        let max_retries = number(MAX_RETRIES_ENV, 5)?;
+
+        Ok(Self {
+            service_account,
+            sheets_api_url,
+            sheet1,
+            sheet2,
+            columns,
+            import_dir,
+            lmdb_path,
+            bind_address,
+            sync_interval,
+            sync_jitter,
+            http_timeout,
+            requests_per_minute,
+            max_retries,
+        })
+    }
+}
+
+fn optional(key: &str) -> Option<String> {
+    std::env::var(key)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn required(key: &'static str) -> Result<String, SettingsError> {
+    optional(key).ok_or(SettingsError::Missing(key))
+}
+
+fn seconds(key: &'static str, default: u64) -> Result<u64, SettingsError> {
+    match optional(key) {
+        Some(raw) =>
+            raw.parse().map_err(|_| SettingsError::Invalid {
+                key,
+                reason: format!("expected a whole number of seconds, got {:?}", raw),
+            }),
+        None => Ok(default),
+    }
+}
+
+fn number(key: &'static str, default: u32) -> Result<u32, SettingsError> {
+    match optional(key) {
+        Some(raw) =>
+            raw.parse().map_err(|_| SettingsError::Invalid {
+                key,
+                reason: format!("expected a whole number, got {:?}", raw),
+            }),
+        None => Ok(default),
+    }
+}
@@ -1,98 +1,152 @@
-use heed::{ Database, Env, EnvOpenOptions };
-use heed::types::SerdeBincode;
-use crate::schema::application::Application;
-use anyhow::{ Result, Context };
-use std::path::Path;
-
-#[derive(Clone)]
-pub struct AppDatabase {
-    env: Env,
-    db: Database<SerdeBincode<String>, SerdeBincode<Application>>,
-}
+use crate::{
+    lmdb::{ error::StorageError, revision::IfMatch, utils::DB },
+    schema::application::Application,
+};
 
+/// Every write is logged to the audit history under `actor` and bumps the
+/// application's revision. Writes taking an `IfMatch` fail with
+/// `RevisionMismatch` when the stored revision isn't accepted.
 pub trait DBApplication: Send + Sync {
-    fn save(&self, app: &Application) -> Result<()>;
-    fn get(&self, id: &str) -> Result<Option<Application>>;
-    fn delete(&self, id: &str) -> Result<()>;
-    fn update(&self, app: &Application) -> Result<()>;
-    fn list(&self) -> Result<Vec<Application>>;
-}
-
-impl AppDatabase {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        std::fs
-            ::read_dir(&path)
-            .is_err()
-            .then(|| {
-                tracing::warn!("Database directory does not exist, creating: {:?}", path.as_ref());
-                std::fs::create_dir_all(&path).expect("Failed to create database directory");
-            });
-
-        let env = unsafe {
-            EnvOpenOptions::new()
-                .map_size(1024 * 1024 * 1024) // 1GB
-                .max_dbs(1)
-                .open(path)?
-        };
-
-        let mut txn = env.write_txn()?;
-        let db = env.create_database(&mut txn, Some("applications"))?;
-        txn.commit()?;
-
-        Ok(Self { env, db })
-    }
-
-    fn with_write_txn<F, T>(&self, f: F) -> Result<T> where F: FnOnce(&mut heed::RwTxn) -> Result<T> {
-        let mut txn = self.env.write_txn()?;
-        let result = f(&mut txn)?;
-        txn.commit()?;
-        Ok(result)
-    }
-
-    fn with_read_txn<F, T>(&self, f: F) -> Result<T> where F: FnOnce(&heed::RoTxn) -> Result<T> {
-        let txn = self.env.read_txn()?;
-        f(&txn)
-    }
+    /// Fails with `Conflict` when an application with the same id exists. Returns the new revision.
+    fn save(&self, app: &Application, actor: &str) -> Result<u64, StorageError>;
+    fn get(&self, id: &str) -> Result<Option<Application>, StorageError>;
+    /// Fails with `NotFound` when there is no such application.
+    fn delete(&self, id: &str, actor: &str, if_match: Option<&IfMatch>) -> Result<(), StorageError>;
+    /// Fails with `NotFound` when there is no such application. Returns the new revision.
+    fn update(&self, app: &Application, actor: &str, if_match: Option<&IfMatch>) -> Result<u64, StorageError>;
+    /// Reads the application, lets `f` change it and stores the result, all in
+    /// one write transaction; nothing is written when `f` fails. Fails with
+    /// `NotFound` when there is no such application.
+    fn modify<E>(
+        &self,
+        id: &str,
+        actor: &str,
+        if_match: Option<&IfMatch>,
+        f: impl FnOnce(&mut Application) -> Result<(), E>
+    ) -> Result<Application, E>
+        where E: From<StorageError> + From<heed::Error>;
+    /// Up to `limit` applications that pass `keep`, in key order (reversed when
+    /// `descending`), starting after the id `after`. Also returns the id of the
+    /// last one when more follow.
+    fn page(
+        &self,
+        descending: bool,
+        after: Option<String>,
+        limit: usize,
+        keep: &dyn Fn(&Application) -> bool
+    ) -> Result<(Vec<Application>, Option<String>), StorageError>;
 }
 
-impl DBApplication for AppDatabase {
-    fn save(&self, app: &Application) -> Result<()> {
-        self.with_write_txn(|txn| {
-            self.db.put(txn, &app.id, app).context("Failed to save application")
+impl DBApplication for DB {
+    fn save(&self, app: &Application, actor: &str) -> Result<u64, StorageError> {
+        self.application_db.write(|txn| {
+            if self.application_db.get_in(txn, &app.id)?.is_some() {
+                return Err(StorageError::Conflict { entity: "application", id: app.id.clone() });
+            }
+            let app = Application { revision: 1, ..app.clone() };
+            self.application_db.put_in(txn, &app.id, &app)?;
+            self.record_audit(txn, "application", &app.id, actor, None, Some(&app))?;
+            Ok(app.revision)
         })
     }
-    fn update(&self, app: &Application) -> Result<()> {
-        self.with_write_txn(|txn| {
-            self.db.put(txn, &app.id, app).context("Failed to update application")
+    fn update(&self, app: &Application, actor: &str, if_match: Option<&IfMatch>) -> Result<u64, StorageError> {
+        self.application_db.write(|txn| {
+            let Some(before) = self.application_db.get_in(txn, &app.id)? else {
+                return Err(StorageError::NotFound { entity: "application", id: app.id.clone() });
+            };
+            IfMatch::check(if_match, "application", &app.id, before.revision)?;
+            let app = Application { revision: before.revision + 1, ..app.clone() };
+            self.application_db.put_in(txn, &app.id, &app)?;
+            self.record_audit(txn, "application", &app.id, actor, Some(&before), Some(&app))?;
+            Ok(app.revision)
         })
     }
-    fn get(&self, id: &str) -> Result<Option<Application>> {
-        self.with_read_txn(|txn| {
-            self.db.get(txn, &id.to_string()).context("Failed to get application")
+    fn modify<E>(
+        &self,
+        id: &str,
+        actor: &str,
+        if_match: Option<&IfMatch>,
+        f: impl FnOnce(&mut Application) -> Result<(), E>
+    ) -> Result<Application, E>
+        where E: From<StorageError> + From<heed::Error>
+    {
+        self.application_db.write(|txn| {
+            let Some(before) = self.application_db.get_in(txn, &id.to_string())? else {
+                return Err(StorageError::NotFound { entity: "application", id: id.to_string() }.into());
+            };
+            IfMatch::check(if_match, "application", id, before.revision)?;
+            let mut app = before.clone();
+            f(&mut app)?;
+            app.revision = before.revision + 1;
+            self.application_db.put_in(txn, &app.id, &app)?;
+            self.record_audit(txn, "application", &app.id, actor, Some(&before), Some(&app))?;
+            Ok(app)
         })
     }
+    fn get(&self, id: &str) -> Result<Option<Application>, StorageError> {
+        Ok(self.application_db.get(&id.to_string())?)
+    }
 
-    fn delete(&self, id: &str) -> Result<()> {
-        self.with_write_txn(|txn| {
-            // Convert &str to &String and map Result<bool, _> to Result<(), _>
-            let id_string = id.to_string();
-            self.db
-                .delete(txn, &id_string)
-                .context("Failed to delete application")
-                .map(|_| ())
+    fn delete(&self, id: &str, actor: &str, if_match: Option<&IfMatch>) -> Result<(), StorageError> {
+        self.application_db.write(|txn| {
+            let Some(before) = self.application_db.get_in(txn, &id.to_string())? else {
+                return Err(StorageError::NotFound { entity: "application", id: id.to_string() });
+            };
+            IfMatch::check(if_match, "application", id, before.revision)?;
+            self.application_db.delete_in(txn, &id.to_string())?;
+            self.record_audit(txn, "application", id, actor, Some(&before), None)
         })
     }
 
-    fn list(&self) -> Result<Vec<Application>> {
-        self.with_read_txn(|txn| {
-            self.db
-                .iter(txn)?
-                .map(|res| {
-                    let (_k, v) = res?;
-                    Ok(v)
-                })
-                .collect::<heed::Result<Vec<_>>>()
-                .context("Failed to list applications")
-        })
+    fn page(
+        &self,
+        descending: bool,
+        after: Option<String>,
+        limit: usize,
+        keep: &dyn Fn(&Application) -> bool
+    ) -> Result<(Vec<Application>, Option<String>), StorageError> {
+        Ok(self.application_db.read(|txn| self.application_db.page_in(txn, descending, after, limit, keep))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lmdb::utils::init_db;
+
+    fn application(id: &str) -> Application {
+        Application {
+            id: id.to_string(),
+            permit_number: "P-1".to_string(),
+            card_ending: 1234,
+            total_paid: 10.0,
+            date: "2024-01-01T00:00:00Z".parse().unwrap(),
+            receipt_no: 7,
+            address: None,
+            version: "v1".to_string(),
+            revision: 0,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            updated_at: "2024-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_in_id_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = init_db(dir.path()).await.unwrap();
+        for id in ["b", "aa", "c", "ab"] {
+            db.save(&application(id), "test").unwrap();
+        }
+
+        let ids = |page: Vec<Application>| page.into_iter().map(|a| a.id).collect::<Vec<_>>();
+        let (first, last) = db.page(false, None, 2, &|_| true).unwrap();
+        assert_eq!(ids(first), ["aa", "ab"]);
+        assert_eq!(last.as_deref(), Some("ab"));
+        let (rest, last) = db.page(false, last, 2, &|_| true).unwrap();
+        assert_eq!(ids(rest), ["b", "c"]);
+        assert_eq!(last, None);
+
+        let (descending, _) = db.page(true, None, 10, &|_| true).unwrap();
+        assert_eq!(ids(descending), ["c", "b", "ab", "aa"]);
     }
 }
@@ -0,0 +1,129 @@
+use std::ops::Bound;
+
+use chrono::Utc;
+use heed::types::{ SerdeJson, Str };
+use heed::{ Database, RwTxn };
+use serde::Serialize;
+use serde_json::{ Map, Value };
+
+use crate::lmdb::{ error::StorageError, utils::DB };
+use crate::schema::audit::{ AuditEntry, AuditOp };
+use crate::schema::sync::FieldChange;
+
+/// Append-only change log. Keys are `{entity}\0{record id}\0{seq}` with the
+/// sequence zero-padded, so a record's entries sit together in order.
+pub type AuditDb = Database<Str, SerdeJson<AuditEntry>>;
+
+fn record_prefix(entity: &str, id: &str) -> String {
+    format!("{}\0{}\0", entity, id)
+}
+
+fn audit_key(entity: &str, id: &str, seq: u64) -> String {
+    format!("{}{:020}", record_prefix(entity, id), seq)
+}
+
+/// The top-level fields that differ, with a missing side as `null`. The
+/// `revision` counter moves on every write and isn't a change of its own.
+fn changes(before: Option<&Value>, after: Option<&Value>) -> Vec<FieldChange> {
+    let empty = Map::new();
+    let fields = |value: Option<&Value>| value.and_then(Value::as_object).cloned().unwrap_or_else(|| empty.clone());
+    let (before, after) = (fields(before), fields(after));
+
+    let mut names: Vec<&String> = before.keys().chain(after.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(|name| *name != "revision" && before.get(*name) != after.get(*name))
+        .map(|name| FieldChange {
+            field: name.clone(),
+            before: before.get(name).cloned().unwrap_or(Value::Null),
+            after: after.get(name).cloned().unwrap_or(Value::Null),
+        })
+        .collect()
+}
+
+pub trait DBAudit {
+    /// Up to `limit` entries for one record, newest first, older than `before`
+    /// (a `seq`). Also returns the `seq` of the last one when more follow.
+    fn history(
+        &self,
+        entity: &str,
+        id: &str,
+        before: Option<u64>,
+        limit: usize
+    ) -> Result<(Vec<AuditEntry>, Option<u64>), StorageError>;
+}
+
+impl DB {
+    /// Appends what changed between `before` and `after` to the record's
+    /// history. Must run in the transaction that writes the record; updates
+    /// that change nothing are not logged.
+    pub(crate) fn record_audit<T: Serialize>(
+        &self,
+        txn: &mut RwTxn,
+        entity: &str,
+        id: &str,
+        actor: &str,
+        before: Option<&T>,
+        after: Option<&T>
+    ) -> Result<(), StorageError> {
+        let op = match (before, after) {
+            (None, _) => AuditOp::Create,
+            (Some(_), Some(_)) => AuditOp::Update,
+            (Some(_), None) => AuditOp::Delete,
+        };
+        let json = |value: Option<&T>| {
+            value.map(serde_json::to_value).transpose().map_err(|e| StorageError::Encode(Box::new(e)))
+        };
+        let changes = changes(json(before)?.as_ref(), json(after)?.as_ref());
+        if op == AuditOp::Update && changes.is_empty() {
+            return Ok(());
+        }
+
+        let prefix = record_prefix(entity, id);
+        let last = self.audit_db.rev_prefix_iter(txn, &prefix)?.next().transpose()?;
+        let seq = last.map_or(0, |(_, entry)| entry.seq) + 1;
+        let entry = AuditEntry {
+            seq,
+            entity: entity.to_string(),
+            record_id: id.to_string(),
+            op,
+            actor: actor.to_string(),
+            at: Utc::now().to_rfc3339(),
+            changes,
+        };
+        Ok(self.audit_db.put(txn, &audit_key(entity, id, seq), &entry)?)
+    }
+}
+
+impl DBAudit for DB {
+    fn history(
+        &self,
+        entity: &str,
+        id: &str,
+        before: Option<u64>,
+        limit: usize
+    ) -> Result<(Vec<AuditEntry>, Option<u64>), StorageError> {
+        let txn = self.env.read_txn()?;
+        let start = record_prefix(entity, id);
+        let end = match before {
+            Some(seq) => audit_key(entity, id, seq),
+            // Sorts right after every key with the prefix
+            None => format!("{}\u{1}", start.trim_end_matches('\0')),
+        };
+        let range = (Bound::Included(start.as_str()), Bound::Excluded(end.as_str()));
+
+        let mut entries = Vec::new();
+        for result in self.audit_db.rev_range(&txn, &range)? {
+            let (_, entry) = result?;
+            // One more entry than fits means there is a next page
+            if entries.len() == limit {
+                let next = entries.last().map(|e: &AuditEntry| e.seq);
+                return Ok((entries, next));
+            }
+            entries.push(entry);
+        }
+        Ok((entries, None))
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{ lmdb::{ error::StorageError, utils::DB }, schema::conflict::SyncConflict };
+
+pub trait DBConflict {
+    fn get_conflict(&self, id: &str) -> Result<Option<SyncConflict>, StorageError>;
+    fn list_conflicts(&self) -> Result<Vec<SyncConflict>, StorageError>;
+    /// Stores `put` and removes `delete` in the caller's transaction.
+    fn update_conflicts_in(
+        &self,
+        txn: &mut heed::RwTxn,
+        put: &[SyncConflict],
+        delete: &[String]
+    ) -> Result<(), StorageError>;
+    /// Returns false when there was no such conflict.
+    fn delete_conflict(&self, id: &str) -> Result<bool, StorageError>;
+}
+
+impl DBConflict for DB {
+    fn get_conflict(&self, id: &str) -> Result<Option<SyncConflict>, StorageError> {
+        Ok(self.conflict_db.get(&id.to_string())?)
+    }
+
+    fn list_conflicts(&self) -> Result<Vec<SyncConflict>, StorageError> {
+        Ok(self.conflict_db.list()?)
+    }
+
+    fn update_conflicts_in(
+        &self,
+        txn: &mut heed::RwTxn,
+        put: &[SyncConflict],
+        delete: &[String]
+    ) -> Result<(), StorageError> {
+        for conflict in put {
+            self.conflict_db.put_in(txn, &conflict.id, conflict)?;
+        }
+        for id in delete {
+            self.conflict_db.delete_in(txn, id)?;
+        }
+        Ok(())
+    }
+
+    fn delete_conflict(&self, id: &str) -> Result<bool, StorageError> {
+        Ok(self.conflict_db.delete(&id.to_string())?)
+    }
+}
@@ -0,0 +1,83 @@
+use heed::{ BoxedError, MdbError };
+use thiserror::Error;
+
+/// Why a storage call failed.
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("{entity} {id} not found")]
+    NotFound {
+        entity: &'static str,
+        id: String,
+    },
+
+    #[error("{entity} {id} already exists")]
+    Conflict {
+        entity: &'static str,
+        id: String,
+    },
+
+    /// The caller's `If-Match` doesn't name the stored revision.
+    #[error("{entity} {id} is at revision {current}")]
+    RevisionMismatch {
+        entity: &'static str,
+        id: String,
+        current: u64,
+    },
+
+    /// The LMDB map size is used up; writes fail until it is raised.
+    #[error("database is full, raise the LMDB map size")]
+    MapFull,
+
+    #[error("database is corrupt: {0}")]
+    Corrupt(MdbError),
+
+    #[error("failed to encode a record: {0}")]
+    Encode(BoxedError),
+
+    /// A stored record no longer matches its type, e.g. after a schema change.
+    #[error("failed to decode a stored record: {0}")]
+    Decode(BoxedError),
+
+    #[error("database I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("LMDB error: {0}")]
+    Lmdb(heed::Error),
+}
+
+impl StorageError {
+    /// Short, stable name of the failure kind, for logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StorageError::NotFound { .. } => "not_found",
+            StorageError::Conflict { .. } => "conflict",
+            StorageError::RevisionMismatch { .. } => "revision_mismatch",
+            StorageError::MapFull => "map_full",
+            StorageError::Corrupt(_) => "corrupt",
+            StorageError::Encode(_) => "encode",
+            StorageError::Decode(_) => "decode",
+            StorageError::Io(_) => "io",
+            StorageError::Lmdb(_) => "lmdb",
+        }
+    }
+}
+
+impl From<heed::Error> for StorageError {
+    fn from(e: heed::Error) -> Self {
+        match e {
+            heed::Error::Io(e) => StorageError::Io(e),
+            heed::Error::Mdb(MdbError::MapFull) => StorageError::MapFull,
+            heed::Error::Mdb(
+                e @ (
+                    MdbError::Corrupted |
+                    MdbError::PageNotFound |
+                    MdbError::Invalid |
+                    MdbError::VersionMismatch
+                ),
+            ) => StorageError::Corrupt(e),
+            heed::Error::Encoding(e) => StorageError::Encode(e),
+            heed::Error::Decoding(e) => StorageError::Decode(e),
+            e => StorageError::Lmdb(e),
+        }
+    }
+}
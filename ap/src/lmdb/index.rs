@@ -0,0 +1,255 @@
+use std::ops::Bound;
+
+use heed::types::{ Str, Unit };
+use heed::{ Database, RoTxn, RwTxn };
+
+use crate::{
+    lmdb::{ error::StorageError, utils::DB },
+    schema::order::{ MatchType, Order, ReturnStatus },
+};
+
+/// Secondary index databases over `orders`. Keys are `{value}\0{order id}` with
+/// no data, so all ids for a value sit next to each other in key order.
+pub type IndexDb = Database<Str, Unit>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderIndex {
+    Marketplace,
+    Status,
+    MatchType,
+    OrderId,
+    Date,
+}
+
+impl OrderIndex {
+    pub const ALL: [OrderIndex; 5] = [
+        OrderIndex::Marketplace,
+        OrderIndex::Status,
+        OrderIndex::MatchType,
+        OrderIndex::OrderId,
+        OrderIndex::Date,
+    ];
+
+    /// LMDB database name.
+    pub fn db_name(self) -> &'static str {
+        match self {
+            OrderIndex::Marketplace => "orders_by_marketplace",
+            OrderIndex::Status => "orders_by_status",
+            OrderIndex::MatchType => "orders_by_match_type",
+            OrderIndex::OrderId => "orders_by_order_id",
+            OrderIndex::Date => "orders_by_date",
+        }
+    }
+
+    /// The indexed value; orders without one are left out of the index.
+    pub fn value(self, order: &Order) -> Option<&str> {
+        match self {
+            OrderIndex::Marketplace => Some(order.marketplace.as_str()),
+            OrderIndex::Status => order.status.map(ReturnStatus::as_str),
+            OrderIndex::MatchType => order.match_type.map(MatchType::as_str),
+            OrderIndex::OrderId => Some(order.order_id.as_str()),
+            OrderIndex::Date => Some(order.date.as_str()),
+        }
+    }
+}
+
+fn index_key(value: &str, id: &str) -> String {
+    format!("{}\0{}", value, id)
+}
+
+impl DB {
+    fn index_db(&self, index: OrderIndex) -> &IndexDb {
+        match index {
+            OrderIndex::Marketplace => &self.marketplace_index,
+            OrderIndex::Status => &self.status_index,
+            OrderIndex::MatchType => &self.match_type_index,
+            OrderIndex::OrderId => &self.order_id_index,
+            OrderIndex::Date => &self.date_index,
+        }
+    }
+
+    /// Moves `order`'s index entries from `before` (the stored version, if any)
+    /// to its current values. Must run in the transaction that writes the order.
+    pub(crate) fn reindex_order(
+        &self,
+        txn: &mut RwTxn,
+        before: Option<&Order>,
+        after: Option<&Order>
+    ) -> heed::Result<()> {
+        for index in OrderIndex::ALL {
+            let old = before.and_then(|o| index.value(o).map(|v| index_key(v, &o.id)));
+            let new = after.and_then(|o| index.value(o).map(|v| index_key(v, &o.id)));
+            if old == new {
+                continue;
+            }
+            if let Some(key) = old {
+                self.index_db(index).delete(txn, &key)?;
+            }
+            if let Some(key) = new {
+                self.index_db(index).put(txn, &key, &())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn scan_orders(
+        &self,
+        txn: &RoTxn,
+        scan: &OrderScan,
+        keep: &dyn Fn(&Order) -> bool
+    ) -> Result<(Vec<Order>, Option<String>), StorageError> {
+        let bound = |key: &Option<String>, bound: fn(String) -> Bound<String>| {
+            key.clone().map_or(Bound::Unbounded, bound)
+        };
+        let (start, end) = match (&scan.after, scan.descending) {
+            (Some(_), false) => (bound(&scan.after, Bound::Excluded), bound(&scan.end, Bound::Excluded)),
+            (Some(_), true) => (bound(&scan.start, Bound::Included), bound(&scan.after, Bound::Excluded)),
+            (None, _) => (bound(&scan.start, Bound::Included), bound(&scan.end, Bound::Excluded)),
+        };
+        let range = (start.as_ref().map(String::as_str), end.as_ref().map(String::as_str));
+
+        let db = self.index_db(scan.index);
+        let keys: Box<dyn Iterator<Item = heed::Result<(&str, ())>>> = if scan.descending {
+            Box::new(db.rev_range(txn, &range)?)
+        } else {
+            Box::new(db.range(txn, &range)?)
+        };
+
+        let mut orders = Vec::new();
+        let mut last_key = None;
+        for result in keys {
+            let (key, ()) = result?;
+            let Some((_, id)) = key.split_once('\0') else {
+                continue;
+            };
+            let Some(order) = self.order_db.get_in(txn, &id.to_string())? else {
+                continue;
+            };
+            if !keep(&order) {
+                continue;
+            }
+            // One more match than fits means there is a next page
+            if orders.len() == scan.limit {
+                return Ok((orders, last_key));
+            }
+            last_key = Some(key.to_string());
+            orders.push(order);
+        }
+        Ok((orders, None))
+    }
+}
+
+/// A walk over one index, in index key order.
+#[derive(Debug, Clone)]
+pub struct OrderScan {
+    pub index: OrderIndex,
+    pub descending: bool,
+    /// Index key of the last order on the previous page.
+    pub after: Option<String>,
+    /// Key bounds: `start` inclusive, `end` exclusive. A bare value works as a
+    /// bound since the id separator sorts below every other character.
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub limit: usize,
+}
+
+pub trait DBOrderIndex {
+    /// Up to `scan.limit` orders that pass `keep`, read through a range iterator
+    /// over the scanned index. Also returns the index key of the last one when
+    /// more orders follow.
+    fn page_orders(
+        &self,
+        scan: &OrderScan,
+        keep: &dyn Fn(&Order) -> bool
+    ) -> Result<(Vec<Order>, Option<String>), StorageError>;
+    /// Drops and rebuilds every index from the `orders` database in one
+    /// transaction. Returns the number of orders indexed.
+    fn rebuild_order_indexes(&self) -> Result<usize, StorageError>;
+}
+
+impl DBOrderIndex for DB {
+    fn page_orders(
+        &self,
+        scan: &OrderScan,
+        keep: &dyn Fn(&Order) -> bool
+    ) -> Result<(Vec<Order>, Option<String>), StorageError> {
+        self.order_db.read(|txn| self.scan_orders(txn, scan, keep))
+    }
+
+    fn rebuild_order_indexes(&self) -> Result<usize, StorageError> {
+        self.order_db.write(|txn| {
+            for index in OrderIndex::ALL {
+                self.index_db(index).clear(txn)?;
+            }
+            let orders = self.order_db.list_in(txn)?;
+            for order in &orders {
+                self.reindex_order(txn, None, Some(order))?;
+            }
+            Ok(orders.len())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lmdb::{ order::DBOrder, utils::init_db };
+
+    fn ids(orders: &[Order]) -> Vec<&str> {
+        orders.iter().map(|o| o.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn prefix_walk_covers_one_value() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = init_db(dir.path()).await.unwrap();
+        for (id, marketplace) in [("1", "shop"), ("2", "shopify"), ("3", "shop"), ("4", "amazon"), ("5", "shop")] {
+            db.insert(Order { marketplace: marketplace.to_string(), ..Order::sample(id) }, "test").unwrap();
+        }
+
+        let mut scan = OrderScan {
+            index: OrderIndex::Marketplace,
+            descending: false,
+            after: None,
+            start: Some("shop".to_string()),
+            end: Some("shop\u{1}".to_string()),
+            limit: 2,
+        };
+        let (first, last) = db.page_orders(&scan, &|_| true).unwrap();
+        assert_eq!(ids(&first), ["1", "3"]);
+        scan.after = last;
+        let (rest, last) = db.page_orders(&scan, &|_| true).unwrap();
+        assert_eq!(ids(&rest), ["5"]);
+        assert_eq!(last, None);
+
+        scan.descending = true;
+        scan.after = None;
+        scan.limit = 10;
+        let (descending, _) = db.page_orders(&scan, &|_| true).unwrap();
+        assert_eq!(ids(&descending), ["5", "3", "1"]);
+    }
+
+    #[tokio::test]
+    async fn index_follows_updates_and_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = init_db(dir.path()).await.unwrap();
+        db.insert(Order { status: Some(ReturnStatus::Received), ..Order::sample("1") }, "test").unwrap();
+        db.insert(Order { status: Some(ReturnStatus::Received), ..Order::sample("2") }, "test").unwrap();
+        db.modify("1", "test", None, |order| {
+            order.status = Some(ReturnStatus::Inspected);
+            Ok::<_, StorageError>(())
+        }).unwrap();
+        db.delete("2".to_string(), "test", None).unwrap();
+
+        let scan = |value: &str| OrderScan {
+            index: OrderIndex::Status,
+            descending: false,
+            after: None,
+            start: Some(value.to_string()),
+            end: Some(format!("{}\u{1}", value)),
+            limit: 10,
+        };
+        assert!(db.page_orders(&scan("received"), &|_| true).unwrap().0.is_empty());
+        assert_eq!(ids(&db.page_orders(&scan("inspected"), &|_| true).unwrap().0), ["1"]);
+    }
+}
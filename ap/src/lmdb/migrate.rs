@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+
+use chrono::{ DateTime, Utc };
+use heed::types::{ Bytes, SerdeBincode };
+use heed::{ BytesDecode, Database, RwTxn };
+use serde::{ de::IgnoredAny, Deserialize };
+
+use crate::lmdb::{ error::StorageError, utils::DB };
+use crate::schema::application::Application;
+use crate::schema::order::{ Order, SheetLabel };
+
+/// An order as bincode stored it before the table moved to JSON.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct LegacyOrder {
+    id: String,
+    marketplace: String,
+    order_id: String,
+    return_order: Option<u64>,
+    shopify_id: Option<String>,
+    market_place_code: Option<String>,
+    returned_sku: Option<String>,
+    offer_sku: Option<String>,
+    matched_sku: Option<String>,
+    match_type: Option<String>,
+    row_number: Option<u32>,
+    manual_confirmation: Option<String>,
+    status: Option<String>,
+    qty: Option<u32>,
+    main_updated: Option<String>,
+    date: String,
+    created_at: String,
+    updated_at: String,
+}
+
+/// An application as bincode stored it before the table moved to JSON.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct LegacyApplication {
+    id: String,
+    permit_number: String,
+    card_ending: i64,
+    total_paid: f64,
+    date: DateTime<Utc>,
+    receipt_no: i64,
+    address: Option<String>,
+    version: String,
+    created_at: String,
+    updated_at: String,
+}
+
+/// Parses a free-text label the way the sheet importer does. Values that
+/// aren't one of the labels are dropped, with a warning.
+fn label<T: SheetLabel>(id: &str, value: Option<String>) -> Option<T> {
+    let value = value.filter(|v| !v.trim().is_empty())?;
+    T::from_cell(&value)
+        .inspect_err(|e| tracing::warn!("Order {}: dropping {} {:?} while migrating: {}", id, T::KIND, value, e))
+        .ok()
+}
+
+impl From<LegacyOrder> for Order {
+    fn from(o: LegacyOrder) -> Self {
+        Order {
+            match_type: label(&o.id, o.match_type),
+            manual_confirmation: label(&o.id, o.manual_confirmation),
+            status: label(&o.id, o.status),
+            id: o.id,
+            marketplace: o.marketplace,
+            order_id: o.order_id,
+            return_order: o.return_order,
+            shopify_id: o.shopify_id,
+            market_place_code: o.market_place_code,
+            returned_sku: o.returned_sku,
+            offer_sku: o.offer_sku,
+            matched_sku: o.matched_sku,
+            row_number: o.row_number,
+            qty: o.qty,
+            main_updated: o.main_updated,
+            source: None,
+            field_meta: BTreeMap::new(),
+            status_history: Vec::new(),
+            revision: 0,
+            date: o.date,
+            created_at: o.created_at,
+            updated_at: o.updated_at,
+        }
+    }
+}
+
+impl From<LegacyApplication> for Application {
+    fn from(a: LegacyApplication) -> Self {
+        Application {
+            id: a.id,
+            permit_number: a.permit_number,
+            card_ending: a.card_ending,
+            total_paid: a.total_paid,
+            date: a.date,
+            receipt_no: a.receipt_no,
+            address: a.address,
+            version: a.version,
+            revision: 0,
+            created_at: a.created_at,
+            updated_at: a.updated_at,
+        }
+    }
+}
+
+/// The entries of `db` that aren't JSON, decoded as `T` from bincode, with
+/// their raw keys.
+fn legacy_entries<T>(txn: &RwTxn, db: Database<Bytes, Bytes>) -> Result<Vec<(Vec<u8>, T)>, StorageError>
+    where T: for<'de> Deserialize<'de>
+{
+    let mut legacy = Vec::new();
+    for entry in db.iter(txn)? {
+        let (key, value) = entry?;
+        if serde_json::from_slice::<IgnoredAny>(value).is_ok() {
+            continue;
+        }
+        let record = SerdeBincode::<T>::bytes_decode(value).map_err(StorageError::Decode)?;
+        legacy.push((key.to_vec(), record));
+    }
+    Ok(legacy)
+}
+
+/// JSON applications still under a bincode-encoded key rather than their raw id.
+fn misplaced_applications(
+    txn: &RwTxn,
+    db: Database<Bytes, Bytes>
+) -> Result<Vec<(Vec<u8>, Application)>, StorageError> {
+    let mut misplaced = Vec::new();
+    for entry in db.iter(txn)? {
+        let (key, value) = entry?;
+        let Ok(app) = serde_json::from_slice::<Application>(value) else {
+            continue;
+        };
+        if key != app.id.as_bytes() {
+            misplaced.push((key.to_vec(), app));
+        }
+    }
+    Ok(misplaced)
+}
+
+impl DB {
+    /// Rewrites orders and applications still stored in the old bincode
+    /// layout as JSON, indexing the orders on the way, and moves applications
+    /// to keys that are their raw id, in one transaction. Records already in
+    /// place are left alone, so this is safe to run on every start. Returns
+    /// how many records moved.
+    pub(crate) fn migrate_legacy_records(&self) -> Result<usize, StorageError> {
+        let mut txn = self.env.write_txn()?;
+        let raw = |txn: &RwTxn, name: &str| -> Result<Database<Bytes, Bytes>, StorageError> {
+            Ok(self.env.open_database(txn, Some(name))?.expect("database created by init_db"))
+        };
+        let (orders, applications) = (raw(&txn, "orders")?, raw(&txn, "applications")?);
+
+        let legacy_orders = legacy_entries::<LegacyOrder>(&txn, orders)?;
+        let mut legacy_applications: Vec<(Vec<u8>, Application)> = legacy_entries::<LegacyApplication>(
+            &txn,
+            applications
+        )?
+            .into_iter()
+            .map(|(key, app)| (key, Application::from(app)))
+            .collect();
+        legacy_applications.extend(misplaced_applications(&txn, applications)?);
+        let moved = legacy_orders.len() + legacy_applications.len();
+
+        for (key, order) in legacy_orders {
+            let order = Order::from(order);
+            orders.delete(&mut txn, &key)?;
+            self.order_db.put_in(&mut txn, &order.id, &order)?;
+            self.reindex_order(&mut txn, None, Some(&order))?;
+        }
+        for (key, app) in legacy_applications {
+            applications.delete(&mut txn, &key)?;
+            self.application_db.put_in(&mut txn, &app.id, &app)?;
+        }
+        txn.commit()?;
+
+        if moved > 0 {
+            tracing::info!("Migrated {} orders and applications to the current layout", moved);
+        }
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use heed::types::SerdeBincode;
+
+    use super::*;
+    use crate::lmdb::{ application::DBApplication, order::DBOrder, utils::init_db };
+    use crate::schema::order::{ MatchType, ReturnStatus };
+
+    fn legacy_order(id: &str, status: &str) -> LegacyOrder {
+        LegacyOrder {
+            id: id.to_string(),
+            marketplace: "shopify".to_string(),
+            order_id: id.to_string(),
+            return_order: None,
+            shopify_id: Some("S-1".to_string()),
+            market_place_code: None,
+            returned_sku: None,
+            offer_sku: None,
+            matched_sku: None,
+            match_type: Some("Automatic".to_string()),
+            row_number: Some(2),
+            manual_confirmation: None,
+            status: Some(status.to_string()),
+            qty: Some(1),
+            main_updated: None,
+            date: "2024-01-01".to_string(),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            updated_at: "2024-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn moves_bincode_records_to_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = init_db(dir.path()).await.unwrap();
+
+        let mut txn = db.env.write_txn().unwrap();
+        let orders: Database<SerdeBincode<String>, SerdeBincode<LegacyOrder>> = db.env
+            .open_database(&txn, Some("orders"))
+            .unwrap()
+            .unwrap();
+        orders.put(&mut txn, &"1".to_string(), &legacy_order("1", " Received ")).unwrap();
+        orders.put(&mut txn, &"2".to_string(), &legacy_order("2", "processed")).unwrap();
+        let applications: Database<SerdeBincode<String>, SerdeBincode<LegacyApplication>> = db.env
+            .open_database(&txn, Some("applications"))
+            .unwrap()
+            .unwrap();
+        let app = LegacyApplication {
+            id: "a1".to_string(),
+            permit_number: "P-1".to_string(),
+            card_ending: 1234,
+            total_paid: 10.0,
+            date: "2024-01-01T00:00:00Z".parse().unwrap(),
+            receipt_no: 7,
+            address: None,
+            version: "v1".to_string(),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            updated_at: "2024-01-01T00:00:00Z".to_string(),
+        };
+        applications.put(&mut txn, &app.id, &app).unwrap();
+        txn.commit().unwrap();
+
+        assert_eq!(db.migrate_legacy_records().unwrap(), 3);
+
+        let order = db.get_single("1".to_string()).unwrap().unwrap();
+        assert_eq!(order.status, Some(ReturnStatus::Received));
+        assert_eq!(order.match_type, Some(MatchType::Automatic));
+        assert_eq!(order.shopify_id.as_deref(), Some("S-1"));
+        // Not a status any more: dropped rather than failing the migration
+        assert_eq!(db.get_single("2".to_string()).unwrap().unwrap().status, None);
+        assert_eq!(DBApplication::get(&db, "a1").unwrap().unwrap().permit_number, "P-1");
+
+        // Already migrated records are left alone
+        assert_eq!(db.migrate_legacy_records().unwrap(), 0);
+    }
+}
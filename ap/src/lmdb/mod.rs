@@ -1,4 +1,14 @@
 pub mod application;
+pub mod audit;
+pub mod conflict;
+pub mod error;
+pub mod index;
+pub mod migrate;
 pub mod order;
+pub mod repository;
+pub mod revision;
+pub mod row_hash;
+pub mod target;
 pub mod user;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod writeback;
\ No newline at end of file
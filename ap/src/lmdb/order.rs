@@ -1,54 +1,126 @@
-use std::error::Error;
-
-use crate::{ lmdb::utils::DB, schema::order::Order };
+use crate::{
+    lmdb::{ error::StorageError, revision::IfMatch, utils::DB },
+    schema::order::Order,
+};
 
+/// Every write is logged to the audit history under `actor` and bumps the
+/// order's revision. Writes taking an `IfMatch` fail with `RevisionMismatch`
+/// when the stored revision isn't accepted; `None` writes unconditionally.
 pub trait DBOrder {
-    fn insert(&self, order: Order) -> Result<(), Box<dyn Error>>;
-    fn get_single(&self, id: String) -> Result<Option<Order>, Box<dyn Error>>;
-    fn get(&self) -> Result<Option<Vec<Order>>, Box<dyn Error>>;
-    fn put(&self, order: Order) -> Result<(), Box<dyn Error>>;
-    fn delete(&self, id: String) -> Result<(), Box<dyn Error>>;
+    /// Fails with `Conflict` when an order with the same id exists. Returns the new revision.
+    fn insert(&self, order: Order, actor: &str) -> Result<u64, StorageError>;
+    fn get_single(&self, id: String) -> Result<Option<Order>, StorageError>;
+    fn get(&self) -> Result<Option<Vec<Order>>, StorageError>;
+    /// Fails with `NotFound` when there is no such order.
+    fn delete(&self, id: String, actor: &str, if_match: Option<&IfMatch>) -> Result<(), StorageError>;
+    /// Reads the order, lets `f` change it and stores the result, all in one
+    /// write transaction; nothing is written when `f` fails. Fails with
+    /// `NotFound` when there is no such order.
+    fn modify<E>(
+        &self,
+        id: &str,
+        actor: &str,
+        if_match: Option<&IfMatch>,
+        f: impl FnOnce(&mut Order) -> Result<(), E>
+    ) -> Result<Order, E>
+        where E: From<StorageError> + From<heed::Error>;
 }
 
-impl DBOrder for DB {
-    fn insert(&self, order: Order) -> Result<(), Box<dyn Error>> {
-        let mut txn = self.env.write_txn()?;
-        self.order_db.put(&mut txn, &order.id, &order)?;
-        txn.commit()?;
-        Ok(())
+impl DB {
+    /// Stores `order` as the next revision after `before` (the stored version,
+    /// if any), moves its index entries and logs the change, in the caller's
+    /// transaction.
+    fn store_order(
+        &self,
+        txn: &mut heed::RwTxn,
+        before: Option<&Order>,
+        order: &mut Order,
+        actor: &str
+    ) -> Result<u64, StorageError> {
+        order.revision = before.map_or(0, |b| b.revision) + 1;
+        self.order_db.put_in(txn, &order.id, order)?;
+        self.reindex_order(txn, before, Some(order))?;
+        self.record_audit(txn, "order", &order.id, actor, before, Some(&*order))?;
+        Ok(order.revision)
     }
-    fn get_single(&self, id: String) -> Result<Option<Order>, Box<dyn Error>> {
-        let txn = self.env.read_txn()?;
-        if let Some(order) = self.order_db.get(&txn, &id)? {
-            Ok(Some(order))
-        } else {
-            Ok(None)
+
+    /// Stores `order` in the caller's transaction if the stored order is still
+    /// at revision `loaded`, or, with `None`, still doesn't exist. Returns false
+    /// and writes nothing when someone changed it since it was read.
+    pub(crate) fn store_order_if_unchanged(
+        &self,
+        txn: &mut heed::RwTxn,
+        loaded: Option<u64>,
+        order: &mut Order,
+        actor: &str
+    ) -> Result<bool, StorageError> {
+        let before = self.order_db.get_in(txn, &order.id)?;
+        match (&before, loaded) {
+            (None, None) => {}
+            (Some(stored), Some(revision)) => {
+                let condition = IfMatch::Revisions(vec![revision]);
+                match IfMatch::check(Some(&condition), "order", &order.id, stored.revision) {
+                    Err(StorageError::RevisionMismatch { .. }) => return Ok(false),
+                    result => result?,
+                }
+            }
+            _ => return Ok(false),
         }
+        self.store_order(txn, before.as_ref(), order, actor)?;
+        Ok(true)
     }
+}
 
-    fn get(&self) -> Result<Option<Vec<Order>>, Box<dyn Error>> {
-        let txn = self.env.read_txn()?;
-        let mut orders = Vec::new();
-        for result in self.order_db.iter(&txn)? {
-            let (_, order) = result?;
-            orders.push(order);
-        }
+impl DBOrder for DB {
+    fn insert(&self, mut order: Order, actor: &str) -> Result<u64, StorageError> {
+        self.order_db.write(|txn| {
+            if self.order_db.get_in(txn, &order.id)?.is_some() {
+                return Err(StorageError::Conflict { entity: "order", id: order.id.clone() });
+            }
+            self.store_order(txn, None, &mut order, actor)
+        })
+    }
+    fn get_single(&self, id: String) -> Result<Option<Order>, StorageError> {
+        Ok(self.order_db.get(&id)?)
+    }
+
+    fn get(&self) -> Result<Option<Vec<Order>>, StorageError> {
+        let orders = self.order_db.list()?;
         if orders.is_empty() {
             Ok(None)
         } else {
-            Ok(Some(orders.clone()))
+            Ok(Some(orders))
         }
     }
-    fn put(&self, order: Order) -> Result<(), Box<dyn Error>> {
-        let mut txn = self.env.write_txn()?;
-        self.order_db.put(&mut txn, &order.id, &order)?;
-        txn.commit()?;
-        Ok(())
+    fn delete(&self, id: String, actor: &str, if_match: Option<&IfMatch>) -> Result<(), StorageError> {
+        self.order_db.write(|txn| {
+            let Some(before) = self.order_db.get_in(txn, &id)? else {
+                return Err(StorageError::NotFound { entity: "order", id: id.clone() });
+            };
+            IfMatch::check(if_match, "order", &id, before.revision)?;
+            self.order_db.delete_in(txn, &id)?;
+            self.reindex_order(txn, Some(&before), None)?;
+            self.record_audit(txn, "order", &id, actor, Some(&before), None)
+        })
     }
-    fn delete(&self, id: String) -> Result<(), Box<dyn Error>> {
-        let mut txn = self.env.write_txn()?;
-        self.order_db.delete(&mut txn, &id)?;
-        txn.commit()?;
-        Ok(())
+    fn modify<E>(
+        &self,
+        id: &str,
+        actor: &str,
+        if_match: Option<&IfMatch>,
+        f: impl FnOnce(&mut Order) -> Result<(), E>
+    ) -> Result<Order, E>
+        where E: From<StorageError> + From<heed::Error>
+    {
+        self.order_db.write(|txn| {
+            let Some(before) = self.order_db.get_in(txn, &id.to_string())? else {
+                return Err(StorageError::NotFound { entity: "order", id: id.to_string() }.into());
+            };
+            IfMatch::check(if_match, "order", id, before.revision)?;
+            let mut order = before.clone();
+            f(&mut order)?;
+            self.store_order(txn, Some(&before), &mut order, actor)?;
+            Ok(order)
+        })
     }
 }
@@ -0,0 +1,160 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::ops::Bound;
+
+use heed::types::SerdeBincode;
+use heed::{ BoxedError, BytesDecode, BytesEncode, Database, Env, RoTxn, RwTxn };
+
+/// `String` keys stored as their UTF-8 bytes, so they sort lexicographically.
+/// (Bincode prefixes the length, which puts shorter keys first.)
+pub enum StrKey {}
+
+impl<'a> BytesEncode<'a> for StrKey {
+    type EItem = String;
+
+    fn bytes_encode(item: &'a String) -> Result<Cow<'a, [u8]>, BoxedError> {
+        Ok(Cow::Borrowed(item.as_bytes()))
+    }
+}
+
+impl<'a> BytesDecode<'a> for StrKey {
+    type DItem = String;
+
+    fn bytes_decode(bytes: &'a [u8]) -> Result<String, BoxedError> {
+        Ok(std::str::from_utf8(bytes)?.to_string())
+    }
+}
+
+/// Typed access to one named database of the shared LMDB environment.
+///
+/// Values are bincode unless `C` says otherwise (`SerdeJson<V>` for records
+/// whose shape is expected to change), and so are keys unless `KC` does
+/// ([`StrKey`] where the key order is visible). The `*_in` methods work inside
+/// a transaction opened with [`read`](Self::read) or [`write`](Self::write), so
+/// several repositories can be updated atomically; the rest open their own.
+pub struct Repository<K, V, C = SerdeBincode<V>, KC = SerdeBincode<K>> {
+    env: Env,
+    db: Database<KC, C>,
+    value: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, C, KC> Clone for Repository<K, V, C, KC> {
+    fn clone(&self) -> Self {
+        Self { env: self.env.clone(), db: self.db, value: PhantomData }
+    }
+}
+
+impl<K, V, C, KC> std::fmt::Debug for Repository<K, V, C, KC> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Repository").field("db", &self.db).finish()
+    }
+}
+
+impl<K, V, C: 'static, KC: 'static> Repository<K, V, C, KC> {
+    /// Opens the database called `name`, creating it if needed.
+    pub fn open(env: &Env, txn: &mut RwTxn, name: &str) -> heed::Result<Self> {
+        let db = env.create_database(txn, Some(name))?;
+        Ok(Self { env: env.clone(), db, value: PhantomData })
+    }
+}
+
+impl<K, V, C, KC> Repository<K, V, C, KC>
+    where
+        K: 'static,
+        C: for<'a> BytesEncode<'a, EItem = V> + for<'a> BytesDecode<'a, DItem = V>,
+        KC: for<'a> BytesEncode<'a, EItem = K> + for<'a> BytesDecode<'a, DItem = K>
+{
+    /// Runs `f` in a read transaction.
+    pub fn read<T, E: From<heed::Error>>(&self, f: impl FnOnce(&RoTxn) -> Result<T, E>) -> Result<T, E> {
+        let txn = self.env.read_txn()?;
+        f(&txn)
+    }
+
+    /// Runs `f` in a write transaction, committed only when `f` succeeds.
+    pub fn write<T, E: From<heed::Error>>(&self, f: impl FnOnce(&mut RwTxn) -> Result<T, E>) -> Result<T, E> {
+        let mut txn = self.env.write_txn()?;
+        let result = f(&mut txn)?;
+        txn.commit()?;
+        Ok(result)
+    }
+
+    pub fn get_in(&self, txn: &RoTxn, key: &K) -> heed::Result<Option<V>> {
+        self.db.get(txn, key)
+    }
+
+    pub fn put_in(&self, txn: &mut RwTxn, key: &K, value: &V) -> heed::Result<()> {
+        self.db.put(txn, key, value)
+    }
+
+    /// Returns false when there was nothing stored under `key`.
+    pub fn delete_in(&self, txn: &mut RwTxn, key: &K) -> heed::Result<bool> {
+        self.db.delete(txn, key)
+    }
+
+    /// Every value, in key order.
+    pub fn list_in(&self, txn: &RoTxn) -> heed::Result<Vec<V>> {
+        let mut values = Vec::new();
+        for result in self.db.iter(txn)? {
+            let (_, value) = result?;
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    /// Every key/value pair, in key order.
+    pub fn entries_in(&self, txn: &RoTxn) -> heed::Result<Vec<(K, V)>> {
+        self.db.iter(txn)?.collect()
+    }
+
+    /// Up to `limit` values that pass `keep`, walking a range iterator in key
+    /// order (reversed when `descending`) from just past `after`. Also returns
+    /// the key of the last value when more follow.
+    pub fn page_in(
+        &self,
+        txn: &RoTxn,
+        descending: bool,
+        after: Option<K>,
+        limit: usize,
+        keep: impl Fn(&V) -> bool
+    ) -> heed::Result<(Vec<V>, Option<K>)> {
+        let after = after.map_or(Bound::Unbounded, Bound::Excluded);
+        let entries: Box<dyn Iterator<Item = heed::Result<(K, V)>>> = if descending {
+            Box::new(self.db.rev_range(txn, &(Bound::Unbounded, after))?)
+        } else {
+            Box::new(self.db.range(txn, &(after, Bound::Unbounded))?)
+        };
+
+        let mut values = Vec::new();
+        let mut last_key = None;
+        for result in entries {
+            let (key, value) = result?;
+            if !keep(&value) {
+                continue;
+            }
+            // One more match than fits means there is a next page
+            if values.len() == limit {
+                return Ok((values, last_key));
+            }
+            last_key = Some(key);
+            values.push(value);
+        }
+        Ok((values, None))
+    }
+
+    pub fn get(&self, key: &K) -> heed::Result<Option<V>> {
+        self.read(|txn| self.get_in(txn, key))
+    }
+
+    #[allow(dead_code)]
+    pub fn put(&self, key: &K, value: &V) -> heed::Result<()> {
+        self.write(|txn| self.put_in(txn, key, value))
+    }
+
+    pub fn delete(&self, key: &K) -> heed::Result<bool> {
+        self.write(|txn| self.delete_in(txn, key))
+    }
+
+    pub fn list(&self) -> heed::Result<Vec<V>> {
+        self.read(|txn| self.list_in(txn))
+    }
+}
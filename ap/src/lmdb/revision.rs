@@ -0,0 +1,28 @@
+use crate::lmdb::error::StorageError;
+
+/// Which stored revisions a conditional write accepts (an `If-Match` header).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfMatch {
+    /// `*`: any, as long as the record exists
+    Any,
+    Revisions(Vec<u64>),
+}
+
+impl IfMatch {
+    /// Fails with `RevisionMismatch` unless `current` is accepted. `None` is
+    /// an unconditional write. Call it inside the write transaction, after
+    /// reading the stored record.
+    pub fn check(
+        condition: Option<&IfMatch>,
+        entity: &'static str,
+        id: &str,
+        current: u64
+    ) -> Result<(), StorageError> {
+        match condition {
+            Some(IfMatch::Revisions(accepted)) if !accepted.contains(&current) => {
+                Err(StorageError::RevisionMismatch { entity, id: id.to_string(), current })
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+use crate::lmdb::error::StorageError;
+use crate::lmdb::utils::DB;
+
+pub trait DBRowHash {
+    fn row_hashes(&self) -> Result<HashMap<String, String>, StorageError>;
+    /// Stores the given hashes in the caller's transaction, replacing any previous value per key.
+    fn put_row_hashes_in(
+        &self,
+        txn: &mut heed::RwTxn,
+        hashes: &HashMap<String, String>
+    ) -> Result<(), StorageError>;
+}
+
+impl DBRowHash for DB {
+    fn row_hashes(&self) -> Result<HashMap<String, String>, StorageError> {
+        let entries = self.row_hash_db.read(|txn| self.row_hash_db.entries_in(txn))?;
+        Ok(entries.into_iter().collect())
+    }
+
+    fn put_row_hashes_in(
+        &self,
+        txn: &mut heed::RwTxn,
+        hashes: &HashMap<String, String>
+    ) -> Result<(), StorageError> {
+        for (key, hash) in hashes {
+            self.row_hash_db.put_in(txn, key, hash)?;
+        }
+        Ok(())
+    }
+}
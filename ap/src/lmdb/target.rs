@@ -0,0 +1,75 @@
+use crate::{ lmdb::{ error::StorageError, utils::DB }, schema::sync_target::SyncTarget };
+
+pub trait DBSyncTarget {
+    fn get_target(&self, id: &str) -> Result<Option<SyncTarget>, StorageError>;
+    fn list_targets(&self) -> Result<Vec<SyncTarget>, StorageError>;
+    /// Fails with `Conflict` when a target with the same id exists.
+    fn insert_target(&self, target: &SyncTarget) -> Result<(), StorageError>;
+    /// Replaces the stored target, keeping its `created_at`, and returns what
+    /// was stored. Fails with `NotFound` when there is no such target.
+    fn update_target(&self, target: &SyncTarget) -> Result<SyncTarget, StorageError>;
+    /// Returns false when there was no such target.
+    fn delete_target(&self, id: &str) -> Result<bool, StorageError>;
+}
+
+impl DBSyncTarget for DB {
+    fn get_target(&self, id: &str) -> Result<Option<SyncTarget>, StorageError> {
+        Ok(self.target_db.get(&id.to_string())?)
+    }
+
+    fn list_targets(&self) -> Result<Vec<SyncTarget>, StorageError> {
+        Ok(self.target_db.list()?)
+    }
+
+    fn insert_target(&self, target: &SyncTarget) -> Result<(), StorageError> {
+        self.target_db.write(|txn| {
+            if self.target_db.get_in(txn, &target.id)?.is_some() {
+                return Err(StorageError::Conflict { entity: "sync target", id: target.id.clone() });
+            }
+            Ok(self.target_db.put_in(txn, &target.id, target)?)
+        })
+    }
+
+    fn update_target(&self, target: &SyncTarget) -> Result<SyncTarget, StorageError> {
+        self.target_db.write(|txn| {
+            let Some(existing) = self.target_db.get_in(txn, &target.id)? else {
+                return Err(StorageError::NotFound { entity: "sync target", id: target.id.clone() });
+            };
+            let target = SyncTarget { created_at: existing.created_at, ..target.clone() };
+            self.target_db.put_in(txn, &target.id, &target)?;
+            Ok(target)
+        })
+    }
+
+    fn delete_target(&self, id: &str) -> Result<bool, StorageError> {
+        Ok(self.target_db.delete(&id.to_string())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lmdb::utils::init_db;
+
+    #[tokio::test]
+    async fn update_keeps_created_at_and_never_recreates() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = init_db(dir.path()).await.unwrap();
+        let target: SyncTarget = serde_json::from_value(serde_json::json!({
+            "id": "eu",
+            "name": "EU",
+            "spreadsheet_id": "sp",
+            "created_at": "2024-01-01T00:00:00Z",
+        })).unwrap();
+        db.insert_target(&target).unwrap();
+
+        let edit = SyncTarget { name: "Europe".to_string(), created_at: String::new(), ..target.clone() };
+        let stored = db.update_target(&edit).unwrap();
+        assert_eq!((stored.name.as_str(), stored.created_at.as_str()), ("Europe", "2024-01-01T00:00:00Z"));
+        assert_eq!(db.get_target("eu").unwrap(), Some(stored));
+
+        assert!(db.delete_target("eu").unwrap());
+        assert!(matches!(db.update_target(&edit), Err(StorageError::NotFound { .. })));
+        assert_eq!(db.get_target("eu").unwrap(), None);
+    }
+}
@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+
+use heed::{ BoxedError, BytesDecode, BytesEncode };
+use secrecy::ExposeSecret;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{ lmdb::{ error::StorageError, utils::DB }, schema::user::User };
+
+/// Users as JSON with the password hash included. `User`'s own `Serialize`
+/// leaves the hash out so it can't reach a response; storage adds it back.
+pub enum UserJson {}
+
+impl<'a> BytesEncode<'a> for UserJson {
+    type EItem = User;
+
+    fn bytes_encode(user: &'a User) -> Result<Cow<'a, [u8]>, BoxedError> {
+        let mut value = serde_json::to_value(user)?;
+        value["password_hash"] = Value::String(user.password_hash.expose_secret().to_string());
+        Ok(Cow::Owned(serde_json::to_vec(&value)?))
+    }
+}
+
+impl<'a> BytesDecode<'a> for UserJson {
+    type DItem = User;
+
+    fn bytes_decode(bytes: &'a [u8]) -> Result<User, BoxedError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Usernames are unique; the check runs in the same transaction as the write.
+#[allow(dead_code)]
+pub trait DBUser {
+    /// Fails with `Conflict` when a user with the same id or username exists.
+    fn insert_user(&self, user: &User) -> Result<(), StorageError>;
+    fn get_user(&self, id: &Uuid) -> Result<Option<User>, StorageError>;
+    fn list_users(&self) -> Result<Vec<User>, StorageError>;
+    /// Fails with `NotFound` when there is no such user, and with `Conflict`
+    /// when another user has the new username.
+    fn update_user(&self, user: &User) -> Result<(), StorageError>;
+    /// Returns false when there was no such user.
+    fn delete_user(&self, id: &Uuid) -> Result<bool, StorageError>;
+}
+
+impl DB {
+    fn check_username_free(&self, txn: &heed::RoTxn, user: &User) -> Result<(), StorageError> {
+        let taken = self.user_db
+            .list_in(txn)?
+            .iter()
+            .any(|other| other.id != user.id && other.username == user.username);
+        if taken {
+            return Err(StorageError::Conflict { entity: "user", id: user.username.clone() });
+        }
+        Ok(())
+    }
+}
+
+impl DBUser for DB {
+    fn insert_user(&self, user: &User) -> Result<(), StorageError> {
+        let id = user.id.to_string();
+        self.user_db.write(|txn| {
+            if self.user_db.get_in(txn, &id)?.is_some() {
+                return Err(StorageError::Conflict { entity: "user", id: id.clone() });
+            }
+            self.check_username_free(txn, user)?;
+            Ok(self.user_db.put_in(txn, &id, user)?)
+        })
+    }
+
+    fn get_user(&self, id: &Uuid) -> Result<Option<User>, StorageError> {
+        Ok(self.user_db.get(&id.to_string())?)
+    }
+
+    fn list_users(&self) -> Result<Vec<User>, StorageError> {
+        Ok(self.user_db.list()?)
+    }
+
+    fn update_user(&self, user: &User) -> Result<(), StorageError> {
+        let id = user.id.to_string();
+        self.user_db.write(|txn| {
+            if self.user_db.get_in(txn, &id)?.is_none() {
+                return Err(StorageError::NotFound { entity: "user", id: id.clone() });
+            }
+            self.check_username_free(txn, user)?;
+            Ok(self.user_db.put_in(txn, &id, user)?)
+        })
+    }
+
+    fn delete_user(&self, id: &Uuid) -> Result<bool, StorageError> {
+        Ok(self.user_db.delete(&id.to_string())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::lmdb::utils::init_db;
+    use crate::schema::user::UserRole;
+
+    fn user(username: &str) -> User {
+        User {
+            id: Uuid::new_v4(),
+            username: username.to_string(),
+            email: format!("{}@example.com", username),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            password_hash: "$argon2id$v=19$hash".into(),
+            role: UserRole::User,
+            version: "v1".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn stores_the_hash_without_serializing_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = init_db(dir.path()).await.unwrap();
+        let alice = user("alice");
+        db.insert_user(&alice).unwrap();
+
+        let stored = db.get_user(&alice.id).unwrap().unwrap();
+        assert_eq!(stored.password_hash.expose_secret(), "$argon2id$v=19$hash");
+        assert_eq!(serde_json::to_value(&stored).unwrap().get("password_hash"), None);
+
+        assert!(matches!(db.insert_user(&alice), Err(StorageError::Conflict { .. })));
+        assert!(matches!(db.insert_user(&User { id: Uuid::new_v4(), ..alice.clone() }), Err(StorageError::Conflict { .. })));
+
+        let bob = user("bob");
+        assert!(matches!(db.update_user(&bob), Err(StorageError::NotFound { .. })));
+        db.insert_user(&bob).unwrap();
+        let renamed = User { username: "alice".to_string(), ..bob.clone() };
+        assert!(matches!(db.update_user(&renamed), Err(StorageError::Conflict { .. })));
+
+        let renamed = User { username: "robert".to_string(), ..bob.clone() };
+        db.update_user(&renamed).unwrap();
+        assert_eq!(db.get_user(&bob.id).unwrap().unwrap().username, "robert");
+        assert_eq!(db.list_users().unwrap().len(), 2);
+
+        assert!(db.delete_user(&alice.id).unwrap());
+        assert!(!db.delete_user(&alice.id).unwrap());
+    }
+}
@@ -1,13 +1,46 @@
-use heed::types::SerdeBincode;
+use heed::types::SerdeJson;
 
-use crate::schema::{ application::Application, order::Order, user::User };
+use crate::lmdb::audit::AuditDb;
+use crate::lmdb::index::{ IndexDb, OrderIndex };
+use crate::lmdb::repository::{ Repository, StrKey };
+use crate::lmdb::user::UserJson;
+
+use crate::schema::{
+    application::Application,
+    conflict::SyncConflict,
+    order::Order,
+    sync::PendingWriteback,
+    sync_target::SyncTarget,
+    user::User,
+};
+/// Every database of the one LMDB environment. Each table is a [`Repository`];
+/// they share the environment, so one write transaction can span several.
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct DB {
     pub env: heed::Env,
-    pub order_db: heed::Database<SerdeBincode<String>, SerdeBincode<Order>>,
-    pub user_db: heed::Database<SerdeBincode<String>, SerdeBincode<User>>,
-    pub application_db: heed::Database<SerdeBincode<String>, SerdeBincode<Application>>,
+    /// JSON, like the other tables whose records grow: bincode is positional,
+    /// so every added field would make the stored orders unreadable.
+    pub order_db: Repository<String, Order, SerdeJson<Order>>,
+    /// Secondary indexes over `order_db`, written in the same transaction as the order.
+    pub marketplace_index: IndexDb,
+    pub status_index: IndexDb,
+    pub match_type_index: IndexDb,
+    pub order_id_index: IndexDb,
+    pub date_index: IndexDb,
+    /// JSON through [`UserJson`], the one encoding that keeps the password hash.
+    pub user_db: Repository<String, User, UserJson>,
+    /// Keyed by the raw id, so `GET /applications` pages in plain id order.
+    pub application_db: Repository<String, Application, SerdeJson<Application>, StrKey>,
+    pub writeback_db: Repository<String, PendingWriteback>,
+    /// Content hash of the last imported version of each sheet row, keyed by source and order id.
+    pub row_hash_db: Repository<String, String>,
+    /// Stored as JSON so new target options don't invalidate existing records.
+    pub target_db: Repository<String, SyncTarget, SerdeJson<SyncTarget>>,
+    /// Open sheet/API conflicts keyed by `{order id}:{field}`. JSON because the values are untyped.
+    pub conflict_db: Repository<String, SyncConflict, SerdeJson<SyncConflict>>,
+    /// Every create, update and delete of orders and applications, written in the same transaction.
+    pub audit_db: AuditDb,
 }
 
 pub async fn init_db<P: AsRef<std::path::Path>>(path: P) -> Result<DB, anyhow::Error> {
@@ -15,27 +48,57 @@ pub async fn init_db<P: AsRef<std::path::Path>>(path: P) -> Result<DB, anyhow::E
         heed::EnvOpenOptions
             ::new()
             .map_size(1024 * 1024 * 1024) // 1GB
-            .max_dbs(3)
+            .max_dbs(16)
             .open(path)?
     };
     let new_env = env.clone();
     let mut txn = new_env.write_txn()?;
-    let order_db = env
-        .create_database(&mut txn, Some("orders"))
+    let order_db = Repository::open(&env, &mut txn, "orders")
         .expect("Failed to create orders database");
-    let user_db = env
-        .create_database(&mut txn, Some("users"))
+    // Indexes created on an existing database start empty; `production_grade reindex` fills them
+    let mut index = |index: OrderIndex| -> IndexDb {
+        env.create_database(&mut txn, Some(index.db_name()))
+            .unwrap_or_else(|e| panic!("Failed to create {} database: {}", index.db_name(), e))
+    };
+    let marketplace_index = index(OrderIndex::Marketplace);
+    let status_index = index(OrderIndex::Status);
+    let match_type_index = index(OrderIndex::MatchType);
+    let order_id_index = index(OrderIndex::OrderId);
+    let date_index = index(OrderIndex::Date);
+    let user_db = Repository::open(&env, &mut txn, "users")
         .expect("Failed to create users database");
-    let application_db = env
-        .create_database(&mut txn, Some("applications"))
+    let application_db = Repository::open(&env, &mut txn, "applications")
         .expect("Failed to create applications database");
+    let writeback_db = Repository::open(&env, &mut txn, "sheet_writeback")
+        .expect("Failed to create sheet_writeback database");
+    let row_hash_db = Repository::open(&env, &mut txn, "sync_row_hashes")
+        .expect("Failed to create sync_row_hashes database");
+    let target_db = Repository::open(&env, &mut txn, "sync_targets")
+        .expect("Failed to create sync_targets database");
+    let conflict_db = Repository::open(&env, &mut txn, "sync_conflicts")
+        .expect("Failed to create sync_conflicts database");
+    let audit_db = env.create_database(&mut txn, Some("audit_log"))
+        .expect("Failed to create audit_log database");
     txn.commit()?;
-    
-    Ok(DB {
+
+    let db = DB {
         env,
         order_db,
+        marketplace_index,
+        status_index,
+        match_type_index,
+        order_id_index,
+        date_index,
         user_db,
         application_db,
-    })
+        writeback_db,
+        row_hash_db,
+        target_db,
+        conflict_db,
+        audit_db,
+    };
+    // Records written before the order and application tables moved to JSON
+    db.migrate_legacy_records()?;
+    Ok(db)
 }
 
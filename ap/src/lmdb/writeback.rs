@@ -0,0 +1,45 @@
+use chrono::Utc;
+
+use crate::{ lmdb::{ error::StorageError, utils::DB }, schema::sync::PendingWriteback };
+
+pub trait DBWriteback {
+    /// Records a failed write-back, bumping the attempt count if the order is already queued.
+    fn enqueue_writeback(&self, id: &str, error: &str) -> Result<(), StorageError>;
+    fn pending_writebacks(&self) -> Result<Vec<PendingWriteback>, StorageError>;
+    fn remove_writeback(&self, id: &str) -> Result<(), StorageError>;
+}
+
+impl DBWriteback for DB {
+    fn enqueue_writeback(&self, id: &str, error: &str) -> Result<(), StorageError> {
+        let now = Utc::now().to_rfc3339();
+        self.writeback_db.write(|txn| {
+            let entry = match self.writeback_db.get_in(txn, &id.to_string())? {
+                Some(mut existing) => {
+                    existing.attempts += 1;
+                    existing.last_error = error.to_string();
+                    existing.last_attempt_at = now;
+                    existing
+                }
+                None =>
+                    PendingWriteback {
+                        id: id.to_string(),
+                        attempts: 1,
+                        last_error: error.to_string(),
+                        queued_at: now.clone(),
+                        last_attempt_at: now,
+                    },
+            };
+            self.writeback_db.put_in(txn, &entry.id, &entry)
+        })?;
+        Ok(())
+    }
+
+    fn pending_writebacks(&self) -> Result<Vec<PendingWriteback>, StorageError> {
+        Ok(self.writeback_db.list()?)
+    }
+
+    fn remove_writeback(&self, id: &str) -> Result<(), StorageError> {
+        self.writeback_db.delete(&id.to_string())?;
+        Ok(())
+    }
+}
@@ -1,19 +1,31 @@
-use actix_web::{ web, App, HttpServer };
+use std::sync::Arc;
+
+use actix_web::{ middleware, web, App, HttpServer };
 use tracing::info;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+mod config;
 mod schema;
 mod routes;
 mod lmdb;
 mod utopia;
 mod scripts;
 
-use crate::lmdb::application::AppDatabase;
-use crate::lmdb::utils::{init_db, DB};
+use crate::config::settings::Settings;
+use crate::lmdb::utils::init_db;
 use crate::routes::application::config as application_config;
+use crate::routes::conflict::conflict_config;
+use crate::routes::error::extractor_config;
 use crate::routes::order::order_config;
-use crate::scripts::google_sheet_order::generate_access_token;
+use crate::routes::request_id::request_id;
+use crate::routes::sync::sync_config;
+use crate::routes::sync_target::sync_target_config;
+use crate::scripts::cli;
+use crate::scripts::scheduler::SyncJob;
+use crate::scripts::sheet_writeback::SheetWriter;
+use crate::scripts::sheets_client::SheetsClient;
+use crate::scripts::token::TokenProvider;
 // use crate::scripts::google_sheet_order::start;
 use crate::utopia::openapi::ApiDoc;
 
@@ -47,22 +59,58 @@ use crate::utopia::openapi::ApiDoc;
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    // Initialize your DB instance here (replace with your actual init)
-    // Run async initialization before server starts
-    generate_access_token().await.expect("Failed to generate access token");
-    let db_path = "./data/lmdb";
-    let db = init_db(db_path).await.expect("Failed to initialize database");
+    env_logger::init();
+
+    // Any arguments mean a one-off command rather than the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() && !cli::needs_settings(&args) {
+        return cli::mock_sheets(&args).await;
+    }
+
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let http = SheetsClient::http_client(settings.http_timeout);
+    let tokens = TokenProvider::new(&settings.service_account, http.clone());
+    let sheets = SheetsClient::new(&settings, http, tokens);
+    let db = init_db(&settings.lmdb_path).await.expect("Failed to initialize database");
+
+    if !args.is_empty() {
+        return cli::run(&args, &settings, &sheets, &db).await;
+    }
+
+    // The sheet import runs in the background so a Google outage can't keep the API down
+    let settings = Arc::new(settings);
+    let writer = SheetWriter::new(settings.clone(), sheets.clone(), db.clone());
+    let sync_job = SyncJob::new(settings.clone(), sheets.clone(), db.clone(), writer.clone());
+    sync_job.clone().spawn();
+
+    info!("Starting server at http://{}", settings.bind_address);
+
     HttpServer::new(move || {
         App::new()
+            .wrap(middleware::from_fn(request_id))
             .app_data(web::Data::new(db.clone()))
+            .app_data(web::Data::new(sheets.clone()))
+            .app_data(web::Data::new(sync_job.clone()))
+            .app_data(web::Data::new(writer.clone()))
+            .configure(extractor_config)
             .configure(application_config)
             .configure(order_config)  // your route config function
+            .configure(sync_config)
+            .configure(sync_target_config)
+            .configure(conflict_config)
             .service(
                 SwaggerUi::new("/docs/{_:.*}")
                     .url("/api-docs/openapi.json", ApiDoc::openapi()),
             )
     })
-    .bind("127.0.0.1:8080")?
+    .bind(&settings.bind_address)?
     .run()
     .await
 }
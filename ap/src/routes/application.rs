@@ -1,6 +1,17 @@
-use actix_web::{ web, HttpResponse, Responder };
+use actix_web::{ http::StatusCode, web, HttpResponse };
+use chrono::{ DateTime, Utc };
+use serde::Deserialize;
+use utoipa::{ IntoParams, ToSchema };
 use crate::schema::application::Application;
-use crate::lmdb::application::{ DBApplication, AppDatabase as DB };
+use crate::schema::page::{ decode_cursor, encode_cursor, page_limit, Page };
+use crate::lmdb::application::DBApplication;
+use crate::lmdb::utils::DB;
+use crate::routes::error::{ ApiError, ProblemDetails };
+use crate::routes::extract::{ Actor, ValidatedJson };
+use crate::routes::history::{ history_page, HistoryQuery };
+use crate::routes::patch::{ apply_patch, MergePatch };
+use crate::routes::precondition::{ etag, Precondition };
+use crate::schema::audit::AuditEntry;
 
 // Handler to create an application
 #[utoipa::path(
@@ -8,15 +19,20 @@ use crate::lmdb::application::{ DBApplication, AppDatabase as DB };
     path = "/applications",
     request_body = Application,
     responses(
-        (status = 201, description = "Application created"),
-        (status = 500, description = "Insert error")
+        (status = 201, description = "Application created", headers(("ETag" = String, description = "The application's first revision"))),
+        (status = 400, description = "Malformed JSON body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Fields failed validation", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Application already exists", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Insert error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn create_application(db: web::Data<DB>, item: web::Json<Application>) -> impl Responder {
-    match db.save(&item.into_inner()) {
-        Ok(_) => HttpResponse::Created().finish(),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Insert error: {}", e)),
-    }
+pub async fn create_application(
+    db: web::Data<DB>,
+    actor: Actor,
+    item: ValidatedJson<Application>
+) -> Result<HttpResponse, ApiError> {
+    let revision = db.save(&item.into_inner(), &actor.0)?;
+    Ok(HttpResponse::Created().insert_header(etag(revision)).finish())
 }
 
 // Handler to get an application by id
@@ -27,69 +43,203 @@ pub async fn create_application(db: web::Data<DB>, item: web::Json<Application>)
         ("id" = String, Path, description = "Application ID")
     ),
     responses(
-        (status = 200, description = "Application found", body = Application),
-        (status = 404, description = "Not found"),
-        (status = 500, description = "Get error")
+        (status = 200, description = "Application found", body = Application, headers(("ETag" = String, description = "The application's revision, for If-Match"))),
+        (status = 404, description = "Application not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Get error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn get_application(db: web::Data<DB>, path: web::Path<String>) -> impl Responder {
-    match db.get(&path.into_inner()) {
-        Ok(Some(app)) => HttpResponse::Ok().json(app),
-        Ok(None) => HttpResponse::NotFound().body("Not found"),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Get error: {}", e)),
+pub async fn get_application(db: web::Data<DB>, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    match db.get(&path.into_inner())? {
+        Some(app) => Ok(HttpResponse::Ok().insert_header(etag(app.revision)).json(app)),
+        None => Err(ApiError::not_found("Application not found")),
     }
 }
 
-// Handler to update an application
+// Handler to update an application, based on the revision named by If-Match
 #[utoipa::path(
     put,
     path = "/applications",
     request_body = Application,
+    params(
+        ("If-Match" = String, Header, description = "ETag of the revision being replaced, or `*`")
+    ),
     responses(
-        (status = 200, description = "Application updated"),
-        (status = 500, description = "Update error")
+        (status = 200, description = "Application updated", headers(("ETag" = String, description = "The application's new revision"))),
+        (status = 400, description = "Malformed JSON body or If-Match", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Fields failed validation", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Application not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The application changed since that ETag", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Update error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn update_application(db: web::Data<DB>, item: web::Json<Application>) -> impl Responder {
-    match db.update(&item.into_inner()) {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Update error: {}", e)),
-    }
+pub async fn update_application(
+    db: web::Data<DB>,
+    actor: Actor,
+    precondition: Precondition,
+    item: ValidatedJson<Application>
+) -> Result<HttpResponse, ApiError> {
+    let revision = db.update(&item.into_inner(), &actor.0, Some(precondition.required()?))?;
+    Ok(HttpResponse::Ok().insert_header(etag(revision)).finish())
 }
 
-// Handler to delete an application by id
+/// Fields a PATCH can't change; the server maintains them.
+const READ_ONLY_FIELDS: &[&str] = &["id", "revision", "created_at", "updated_at"];
+
+// Handler to change some fields of an application with a JSON Merge Patch
+// (RFC 7396); `updated_at` moves when anything changed
+#[utoipa::path(
+    patch,
+    path = "/applications/{id}",
+    params(
+        ("id" = String, Path, description = "Application ID"),
+        ("If-Match" = String, Header, description = "ETag of the revision being patched, or `*`")
+    ),
+    request_body(
+        content = Application,
+        content_type = "application/merge-patch+json",
+        description = "Only the fields to change; `null` clears an optional field",
+        example = json!({ "address": "456 Side St, City" })
+    ),
+    responses(
+        (status = 200, description = "Application patched", body = Application, headers(("ETag" = String, description = "The application's new revision"))),
+        (status = 400, description = "Malformed patch or If-Match", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Application not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The application changed since that ETag", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "The patch isn't JSON", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The patched application failed validation, or a read-only field was changed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Update error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn patch_application(
+    db: web::Data<DB>,
+    actor: Actor,
+    precondition: Precondition,
+    path: web::Path<String>,
+    patch: MergePatch
+) -> Result<HttpResponse, ApiError> {
+    let if_match = precondition.required()?;
+    let app = db.modify(&path.into_inner(), &actor.0, Some(if_match), |app| {
+        let mut patched = apply_patch(app, &patch, READ_ONLY_FIELDS)?;
+        if patched != *app {
+            patched.updated_at = Utc::now().to_rfc3339();
+        }
+        *app = patched;
+        Ok::<_, ApiError>(())
+    })?;
+    Ok(HttpResponse::Ok().insert_header(etag(app.revision)).json(app))
+}
+
+// Handler to delete an application by id, at the revision named by If-Match
 #[utoipa::path(
     delete,
     path = "/applications/{id}",
     params(
-        ("id" = String, Path, description = "Application ID")
+        ("id" = String, Path, description = "Application ID"),
+        ("If-Match" = String, Header, description = "ETag of the revision being deleted, or `*`")
     ),
     responses(
         (status = 200, description = "Application deleted"),
-        (status = 500, description = "Delete error")
+        (status = 400, description = "Malformed If-Match", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Application not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The application changed since that ETag", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Delete error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn delete_application(db: web::Data<DB>, path: web::Path<String>) -> impl Responder {
-    match db.delete(&path.into_inner()) {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Delete error: {}", e)),
+pub async fn delete_application(
+    db: web::Data<DB>,
+    actor: Actor,
+    precondition: Precondition,
+    path: web::Path<String>
+) -> Result<HttpResponse, ApiError> {
+    db.delete(&path.into_inner(), &actor.0, Some(precondition.required()?))?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+// Handler to browse the change history of an application, newest first
+#[utoipa::path(
+    get,
+    path = "/applications/{id}/history",
+    params(
+        ("id" = String, Path, description = "Application ID"),
+        HistoryQuery
+    ),
+    responses(
+        (status = 200, description = "One page of changes", body = Page<AuditEntry>),
+        (status = 400, description = "Invalid cursor", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No history for this application", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "History error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_application_history(
+    db: web::Data<DB>,
+    path: web::Path<String>,
+    query: web::Query<HistoryQuery>
+) -> Result<HttpResponse, ApiError> {
+    history_page(&db, "application", &path.into_inner(), &query)
+}
+
+/// Sort for `GET /applications`: by id, or `-id` for descending.
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+pub enum ApplicationSort {
+    #[default]
+    #[serde(rename = "id")]
+    Id,
+    #[serde(rename = "-id")]
+    IdDesc,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ApplicationQuery {
+    permit_number: Option<String>,
+    /// Applications dated at or after this time
+    date_from: Option<DateTime<Utc>>,
+    /// Applications dated at or before this time
+    date_to: Option<DateTime<Utc>>,
+    #[param(inline)]
+    sort: Option<ApplicationSort>,
+    /// `next_cursor` from the previous page
+    cursor: Option<String>,
+    /// Page size, 1-500 (default 50)
+    limit: Option<usize>,
+}
+
+impl ApplicationQuery {
+    fn matches(&self, app: &Application) -> bool {
+        self.permit_number.as_ref().is_none_or(|p| app.permit_number == *p) &&
+            self.date_from.is_none_or(|from| app.date >= from) &&
+            self.date_to.is_none_or(|to| app.date <= to)
     }
 }
 
-// Handler to list all applications (for demo, not efficient for large datasets)
+// Handler to list applications a page at a time, ordered by id
 #[utoipa::path(
     get,
     path = "/applications",
+    params(ApplicationQuery),
     responses(
-        (status = 200, description = "List all applications", body = [Application]),
-        (status = 500, description = "List error")
+        (status = 200, description = "One page of applications", body = Page<Application>),
+        (status = 400, description = "Invalid cursor", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "List error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn list_applications(db: web::Data<DB>) -> impl Responder {
-    match db.list() {
-        Ok(applications) => HttpResponse::Ok().json(applications),
-        Err(e) => HttpResponse::InternalServerError().body(format!("List error: {}", e)),
-    }
+pub async fn list_applications(db: web::Data<DB>, query: web::Query<ApplicationQuery>) -> Result<HttpResponse, ApiError> {
+    let (descending, sort) = match query.sort.unwrap_or_default() {
+        ApplicationSort::Id => (false, "id"),
+        ApplicationSort::IdDesc => (true, "-id"),
+    };
+    let after = query.cursor
+        .as_deref()
+        .map(|c| decode_cursor(sort, c))
+        .transpose()
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, "invalid_cursor", e))?;
+
+    let (items, last_id) = db.page(descending, after, page_limit(query.limit), &|app| query.matches(app))?;
+    let next_cursor = last_id.map(|id| encode_cursor(sort, &id));
+    Ok(HttpResponse::Ok().json(Page { items, next_cursor }))
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
@@ -103,8 +253,9 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         web
             ::resource("/applications/{id}")
             .route(web::get().to(get_application))
+            .route(web::patch().to(patch_application))
             .route(web::delete().to(delete_application))
-    );
+    ).service(web::resource("/applications/{id}/history").route(web::get().to(get_application_history)));
 }
 
         
\ No newline at end of file
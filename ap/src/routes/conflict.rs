@@ -0,0 +1,142 @@
+use actix_web::{ http::StatusCode, web, HttpResponse, ResponseError };
+use chrono::Utc;
+
+use crate::lmdb::conflict::DBConflict;
+use crate::lmdb::order::DBOrder;
+use crate::lmdb::utils::DB;
+use crate::routes::error::{ ApiError, ProblemDetails };
+use crate::routes::extract::Actor;
+use crate::routes::precondition::{ etag, Precondition };
+use crate::schema::conflict::{ ResolveConflict, Resolution, SyncConflict };
+use crate::scripts::conflicts::{ field_values, resolve };
+use crate::scripts::sheet_writeback::SheetWriter;
+
+/// List open sync conflicts
+///
+/// Fields edited both in a sheet and through the API since the last sync,
+/// oldest first. The sync leaves these fields alone until they are resolved.
+#[utoipa::path(
+    get,
+    path = "/conflicts",
+    responses(
+        (status = 200, description = "Open conflicts", body = [SyncConflict]),
+        (status = 500, description = "List error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn list_conflicts(db: web::Data<DB>) -> Result<HttpResponse, ApiError> {
+    let mut conflicts = db.list_conflicts()?;
+    conflicts.sort_by(|a, b| a.detected_at.cmp(&b.detected_at).then_with(|| a.id.cmp(&b.id)));
+    Ok(HttpResponse::Ok().json(conflicts))
+}
+
+/// Get a sync conflict by id
+#[utoipa::path(
+    get,
+    path = "/conflicts/{id}",
+    params(
+        ("id" = String, Path, description = "Conflict ID, `{order id}:{field}`")
+    ),
+    responses(
+        (status = 200, description = "Conflict found", body = SyncConflict),
+        (status = 404, description = "Conflict not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Get error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_conflict(db: web::Data<DB>, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    match db.get_conflict(&path.into_inner())? {
+        Some(conflict) => Ok(HttpResponse::Ok().json(conflict)),
+        None => Err(ApiError::not_found("Conflict not found")),
+    }
+}
+
+/// Resolve a sync conflict
+///
+/// `sheet` takes the sheet's value, `api` keeps the order's current value and
+/// `value` sets the given one. The latter two are written back to the sheet.
+/// A status goes through the same transition rules as
+/// `POST /orders/{id}/transition` and is recorded in the status history.
+/// `If-Match` must carry the ETag of the order the resolution is based on.
+#[utoipa::path(
+    post,
+    path = "/conflicts/{id}/resolve",
+    params(
+        ("id" = String, Path, description = "Conflict ID, `{order id}:{field}`"),
+        ("If-Match" = String, Header, description = "ETag of the order's current revision, or `*`")
+    ),
+    request_body = ResolveConflict,
+    responses(
+        (status = 200, description = "Conflict resolved, sheet write-back started where needed", headers(("ETag" = String, description = "The order's new revision"))),
+        (status = 400, description = "Missing or invalid value, or malformed If-Match", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Conflict or order not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The resolved status isn't an allowed move from the current one", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The order changed since that ETag", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Resolve error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn resolve_conflict(
+    db: web::Data<DB>,
+    writer: web::Data<SheetWriter>,
+    actor: Actor,
+    precondition: Precondition,
+    path: web::Path<String>,
+    item: web::Json<ResolveConflict>
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let request = item.into_inner();
+
+    let if_match = precondition.required()?;
+    let conflict = db.get_conflict(&id)?.ok_or_else(|| ApiError::not_found("Conflict not found"))?;
+    if request.resolution == Resolution::Value && request.value.is_none() {
+        return Err(ApiError::invalid_field("value", "required when resolution is value"));
+    }
+
+    // The revision is checked and the value picked in the order's write transaction
+    let resolved = db
+        .modify(&conflict.order_id, &actor.0, Some(if_match), |order| {
+            let value = match request.resolution {
+                Resolution::Sheet => conflict.sheet_value.clone(),
+                Resolution::Api => field_values(order).remove(&conflict.field).unwrap_or_default(),
+                Resolution::Value => request.value.clone().unwrap_or_default(),
+            };
+            let now = Utc::now().to_rfc3339();
+            let mut resolved = resolve(order, &conflict, request.resolution, value, &now).map_err(|e| {
+                ApiError::invalid_field("value", format!("invalid for {}: {}", conflict.field, e))
+            })?;
+            if resolved.status.is_none() && order.status.is_some() {
+                return Err(ApiError::invalid_field("value", "the status can't be cleared"));
+            }
+            // A status resolution is a move like any other
+            let note = format!("resolved sync conflict {}", conflict.id);
+            resolved
+                .confirm_status_change(order, &actor.0, Some(note), &now)
+                .map_err(|e| ApiError::conflict("illegal_transition", e.to_string()))?;
+            *order = resolved;
+            Ok::<_, ApiError>(())
+        })
+        .map_err(|e| {
+            if e.status_code() == StatusCode::NOT_FOUND {
+                // Nothing left to resolve against
+                let _ = db.delete_conflict(&id);
+                return ApiError::not_found(format!("Order {} no longer exists", conflict.order_id));
+            }
+            e
+        })?;
+    db.delete_conflict(&id)?;
+
+    // The sheet already holds its own value
+    if request.resolution == Resolution::Sheet {
+        return Ok(
+            HttpResponse::Ok().insert_header(etag(resolved.revision)).body("Conflict resolved with the sheet's value")
+        );
+    }
+    writer.spawn_write(resolved.id.clone());
+    Ok(HttpResponse::Ok().insert_header(etag(resolved.revision)).body("Conflict resolved, sheet write-back started"))
+}
+
+/// Configure routes for sync conflicts
+pub fn conflict_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/conflicts").route(web::get().to(list_conflicts)))
+        .service(web::resource("/conflicts/{id}").route(web::get().to(get_conflict)))
+        .service(web::resource("/conflicts/{id}/resolve").route(web::post().to(resolve_conflict)));
+}
@@ -0,0 +1,148 @@
+use actix_web::{ http::StatusCode, HttpResponse, ResponseError };
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::lmdb::error::StorageError;
+use crate::routes::request_id;
+
+/// What went wrong with one field of a request.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    #[schema(example = "marketplace")]
+    pub field: String,
+    #[schema(example = "must not be empty")]
+    pub message: String,
+}
+
+/// RFC 7807 body of every error response, sent as `application/problem+json`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    pub type_: String,
+    /// The HTTP reason phrase
+    #[schema(example = "Not Found")]
+    pub title: String,
+    #[schema(example = 404)]
+    pub status: u16,
+    /// Stable, machine-readable error code
+    #[schema(example = "not_found")]
+    pub code: String,
+    #[schema(example = "order 123 not found")]
+    pub detail: String,
+    /// Also sent in the `x-request-id` response header
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// The error type of every route handler.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    errors: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into(), errors: Vec::new() }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    pub fn conflict(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, code, message)
+    }
+
+    /// A 400 blaming one field of the request body.
+    pub fn invalid_field(field: &str, message: impl Into<String>) -> Self {
+        let message = message.into();
+        let mut error = Self::new(StatusCode::BAD_REQUEST, "invalid_field", format!("{}: {}", field, message));
+        error.errors.push(FieldError { field: field.to_string(), message });
+        error
+    }
+
+    /// A 422 listing every field that failed validation.
+    pub fn validation(errors: Vec<FieldError>) -> Self {
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        let message = format!("Invalid fields: {}", fields.join(", "));
+        Self { errors, ..Self::new(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed", message) }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status)
+            .content_type("application/problem+json")
+            .json(ProblemDetails {
+                type_: "about:blank".to_string(),
+                title: self.status.canonical_reason().unwrap_or("Error").to_string(),
+                status: self.status.as_u16(),
+                code: self.code.to_string(),
+                detail: self.message.clone(),
+                request_id: request_id::current(),
+                errors: self.errors.clone(),
+            })
+    }
+}
+
+/// Missing, duplicate and since-changed records keep their message; anything
+/// else is logged and answered without the internal details. Counted by kind
+/// either way.
+impl From<StorageError> for ApiError {
+    fn from(e: StorageError) -> Self {
+        metrics::counter!("storage_errors_total", "kind" => e.kind()).increment(1);
+        match e {
+            StorageError::NotFound { .. } => ApiError::not_found(e.to_string()),
+            StorageError::Conflict { .. } => ApiError::conflict("already_exists", e.to_string()),
+            StorageError::RevisionMismatch { .. } => {
+                ApiError::new(StatusCode::PRECONDITION_FAILED, "revision_mismatch", e.to_string())
+            }
+            StorageError::MapFull => {
+                tracing::error!("Storage error: {}", e);
+                ApiError::new(StatusCode::INSUFFICIENT_STORAGE, "storage_full", "The database is full")
+            }
+            _ => {
+                tracing::error!("Storage error: {}", e);
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", "Storage error")
+            }
+        }
+    }
+}
+
+impl From<heed::Error> for ApiError {
+    fn from(e: heed::Error) -> Self {
+        StorageError::from(e).into()
+    }
+}
+
+/// Bad JSON bodies, query strings and path segments get the same problem+json
+/// shape as handler errors.
+pub fn extractor_config(cfg: &mut actix_web::web::ServiceConfig) {
+    use actix_web::web::{ JsonConfig, PathConfig, QueryConfig };
+
+    cfg.app_data(
+        JsonConfig::default().error_handler(|e, _| {
+            ApiError::new(StatusCode::BAD_REQUEST, "invalid_body", e.to_string()).into()
+        })
+    )
+        .app_data(
+            QueryConfig::default().error_handler(|e, _| {
+                ApiError::new(StatusCode::BAD_REQUEST, "invalid_query", e.to_string()).into()
+            })
+        )
+        .app_data(
+            PathConfig::default().error_handler(|e, _| {
+                ApiError::new(StatusCode::BAD_REQUEST, "invalid_path", e.to_string()).into()
+            })
+        );
+}
@@ -0,0 +1,106 @@
+use std::future::{ ready, Future, Ready };
+use std::pin::Pin;
+
+use actix_web::{ dev::Payload, http::StatusCode, web, FromRequest, HttpRequest };
+use serde::de::DeserializeOwned;
+use validator::{ Validate, ValidationError, ValidationErrors };
+
+use crate::routes::error::{ ApiError, FieldError };
+
+/// JSON body that has also passed its `validator` rules. A body that doesn't
+/// parse is a 400, one that breaks a rule a 422 naming every offending field.
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = web::Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            let value = json.await?.into_inner();
+            check_valid(&value)?;
+            Ok(ValidatedJson(value))
+        })
+    }
+}
+
+/// Runs `value`'s `validator` rules; a 422 naming every offending field when
+/// any is broken.
+pub fn check_valid<T: Validate>(value: &T) -> Result<(), ApiError> {
+    value.validate().map_err(|e| ApiError::validation(field_errors(&e)))
+}
+
+/// Every broken rule as `field message`, comma separated, for reports that
+/// aren't a response.
+pub fn describe_invalid(errors: &ValidationErrors) -> String {
+    field_errors(errors)
+        .iter()
+        .map(|e| format!("{} {}", e.field, e.message))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// One entry per broken rule, sorted by field.
+fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut fields: Vec<FieldError> = errors
+        .field_errors()
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors.iter().map(move |e| FieldError { field: field.to_string(), message: describe(e) })
+        })
+        .collect();
+    fields.sort_by(|a, b| a.field.cmp(&b.field));
+    fields
+}
+
+fn describe(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+    let param = |name: &str| error.params.get(name).map(|v| v.to_string());
+    match (error.code.as_ref(), param("min"), param("max")) {
+        ("length", Some(min), Some(max)) => format!("must be {} to {} characters long", min, max),
+        ("length", Some(min), None) => format!("must be at least {} characters long", min),
+        ("length", None, Some(max)) => format!("must be at most {} characters long", max),
+        ("range", Some(min), Some(max)) => format!("must be between {} and {}", min, max),
+        ("range", Some(min), None) => format!("must be at least {}", min),
+        ("range", None, Some(max)) => format!("must be at most {}", max),
+        ("regex", _, _) => "has an invalid format".to_string(),
+        ("email", _, _) => "must be a valid email address".to_string(),
+        (code, _, _) => format!("failed the {} check", code),
+    }
+}
+
+/// Who is making the request, for the audit history: the `x-actor` header,
+/// or `api` when there is none.
+#[derive(Debug, Clone)]
+pub struct Actor(pub String);
+
+impl FromRequest for Actor {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Some(value) = req.headers().get("x-actor") else {
+            return ready(Ok(Actor("api".to_string())));
+        };
+        let actor = value
+            .to_str()
+            .ok()
+            .map(str::trim)
+            .filter(|a| !a.is_empty() && a.len() <= 64)
+            .map(|a| Actor(a.to_string()))
+            .ok_or_else(|| {
+                ApiError::new(StatusCode::BAD_REQUEST, "invalid_actor", "x-actor must be 1-64 printable characters")
+            });
+        ready(actor)
+    }
+}
@@ -0,0 +1,35 @@
+use actix_web::{ http::StatusCode, HttpResponse };
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::lmdb::audit::DBAudit;
+use crate::lmdb::utils::DB;
+use crate::routes::error::ApiError;
+use crate::schema::page::{ decode_cursor, encode_cursor, page_limit, Page };
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    /// `next_cursor` from the previous page
+    cursor: Option<String>,
+    /// Page size, 1-500 (default 50)
+    limit: Option<usize>,
+}
+
+/// One page of a record's audit history, newest first. A record that never
+/// had any is a 404; a deleted one still has its history.
+pub fn history_page(db: &DB, entity: &str, id: &str, query: &HistoryQuery) -> Result<HttpResponse, ApiError> {
+    let before = query.cursor
+        .as_deref()
+        .map(|c| decode_cursor("history", c))
+        .transpose()
+        .and_then(|seq| seq.map(|s| s.parse::<u64>().map_err(|_| "Invalid cursor".to_string())).transpose())
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, "invalid_cursor", e))?;
+
+    let (items, last_seq) = db.history(entity, id, before, page_limit(query.limit))?;
+    if items.is_empty() && before.is_none() {
+        return Err(ApiError::not_found(format!("No history for {} {}", entity, id)));
+    }
+    let next_cursor = last_seq.map(|seq| encode_cursor("history", &seq.to_string()));
+    Ok(HttpResponse::Ok().json(Page { items, next_cursor }))
+}
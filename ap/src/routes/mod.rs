@@ -1,2 +1,11 @@
 pub mod application;
-pub mod order;
\ No newline at end of file
+pub mod conflict;
+pub mod error;
+pub mod extract;
+pub mod history;
+pub mod order;
+pub mod patch;
+pub mod precondition;
+pub mod request_id;
+pub mod sync;
+pub mod sync_target;
\ No newline at end of file
@@ -1,5 +1,52 @@
-use actix_web::{web, HttpResponse, Responder};
-use crate::{lmdb::{order::DBOrder, utils::DB}, schema::order::Order, scripts::utils::update_rows_for_order};
+use actix_web::{http::StatusCode, web, HttpResponse};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use crate::{
+    lmdb::{index::{DBOrderIndex, OrderIndex, OrderScan}, order::DBOrder, utils::DB},
+    routes::{error::{ApiError, FieldError, ProblemDetails}, extract::{Actor, ValidatedJson}, history::{history_page, HistoryQuery}},
+    routes::patch::{apply_patch, MergePatch},
+    routes::precondition::{etag, Precondition},
+    schema::audit::AuditEntry,
+    schema::order::{MatchType, Order, ReturnStatus, TransitionRequest},
+    schema::page::{decode_cursor, encode_cursor, page_limit, Page},
+    scripts::conflicts::{fields_differ, track_api_edit},
+    scripts::sheet_writeback::SheetWriter,
+};
+
+/// A new order starts without a status or as `received`.
+fn check_new_status(order: &Order) -> Result<(), ApiError> {
+    match order.status {
+        Some(status) if !ReturnStatus::can_transition(None, status) => Err(ApiError::validation(vec![FieldError {
+            field: "status".to_string(),
+            message: "a new order starts as received".to_string(),
+        }])),
+        _ => Ok(()),
+    }
+}
+
+/// Fields a PATCH can't change; the server maintains them, and `source`, the
+/// sync target that owns the order, is set by the sync.
+const READ_ONLY_FIELDS: &[&str] = &[
+    "id",
+    "source",
+    "field_meta",
+    "status_history",
+    "revision",
+    "created_at",
+    "updated_at",
+];
+
+/// Updates keep the status; it only moves through `POST /orders/{id}/transition`.
+fn check_status_unchanged(before: &Order, after: &Order) -> Result<(), ApiError> {
+    if after.status == before.status {
+        return Ok(());
+    }
+    Err(ApiError::validation(vec![FieldError {
+        field: "status".to_string(),
+        message: format!("change it with POST /orders/{}/transition", before.id),
+    }]))
+}
 
 /// Insert a new Order
 #[utoipa::path(
@@ -7,15 +54,25 @@ use crate::{lmdb::{order::DBOrder, utils::DB}, schema::order::Order, scripts::ut
     path = "/orders",
     request_body = Order,
     responses(
-        (status = 201, description = "Order inserted successfully"),
-        (status = 500, description = "Insert error")
+        (status = 201, description = "Order inserted successfully", headers(("ETag" = String, description = "The order's first revision"))),
+        (status = 400, description = "Malformed JSON body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Fields failed validation", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Order already exists", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Insert error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn insert_order(db: web::Data<DB>, item: web::Json<Order>) -> impl Responder {
-    match db.insert(item.into_inner()) {
-        Ok(_) => HttpResponse::Created().finish(),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Insert error: {}", e)),
-    }
+pub async fn insert_order(
+    db: web::Data<DB>,
+    actor: Actor,
+    item: ValidatedJson<Order>
+) -> Result<HttpResponse, ApiError> {
+    let mut order = item.into_inner();
+    // Provenance is only ever recorded by the server
+    order.field_meta.clear();
+    order.status_history.clear();
+    check_new_status(&order)?;
+    let revision = db.insert(order, &actor.0)?;
+    Ok(HttpResponse::Created().insert_header(etag(revision)).finish())
 }
 
 /// Get single Order by id
@@ -26,102 +83,371 @@ pub async fn insert_order(db: web::Data<DB>, item: web::Json<Order>) -> impl Res
         ("id" = String, Path, description = "Order ID")
     ),
     responses(
-        (status = 200, description = "Order found", body = Order),
-        (status = 404, description = "Order not found"),
-        (status = 500, description = "Get error")
+        (status = 200, description = "Order found", body = Order, headers(("ETag" = String, description = "The order's revision, for If-Match"))),
+        (status = 404, description = "Order not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Get error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn get_order(db: web::Data<DB>, path: web::Path<String>) -> impl Responder {
-    match db.get_single(path.into_inner()) {
-        Ok(Some(order)) => HttpResponse::Ok().json(order),
-        Ok(None) => HttpResponse::NotFound().body("Order not found"),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Get error: {}", e)),
+pub async fn get_order(db: web::Data<DB>, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    match db.get_single(path.into_inner())? {
+        Some(order) => Ok(HttpResponse::Ok().insert_header(etag(order.revision)).json(order)),
+        None => Err(ApiError::not_found("Order not found")),
+    }
+}
+
+/// Sort orders for `GET /orders`; a leading `-` means descending.
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+pub enum OrderSort {
+    #[default]
+    #[serde(rename = "order_id")]
+    OrderId,
+    #[serde(rename = "-order_id")]
+    OrderIdDesc,
+    #[serde(rename = "date")]
+    Date,
+    #[serde(rename = "-date")]
+    DateDesc,
+}
+
+impl OrderSort {
+    /// The index the page is read from, the direction, and the name cursors are tied to.
+    fn scan(self) -> (OrderIndex, bool, &'static str) {
+        match self {
+            OrderSort::OrderId => (OrderIndex::OrderId, false, "order_id"),
+            OrderSort::OrderIdDesc => (OrderIndex::OrderId, true, "-order_id"),
+            OrderSort::Date => (OrderIndex::Date, false, "date"),
+            OrderSort::DateDesc => (OrderIndex::Date, true, "-date"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OrderQuery {
+    marketplace: Option<String>,
+    #[param(inline)]
+    status: Option<ReturnStatus>,
+    #[param(inline)]
+    match_type: Option<MatchType>,
+    order_id: Option<String>,
+    /// Orders dated on or after this day
+    date_from: Option<NaiveDate>,
+    /// Orders dated on or before this day
+    date_to: Option<NaiveDate>,
+    #[param(inline)]
+    sort: Option<OrderSort>,
+    /// `next_cursor` from the previous page
+    cursor: Option<String>,
+    /// Page size, 1-500 (default 50)
+    limit: Option<usize>,
+}
+
+impl OrderQuery {
+    /// The first exact filter with its own index, which then bounds the walk.
+    fn filter_index(&self) -> Option<(OrderIndex, &str)> {
+        [
+            (OrderIndex::Marketplace, self.marketplace.as_deref()),
+            (OrderIndex::Status, self.status.map(ReturnStatus::as_str)),
+            (OrderIndex::MatchType, self.match_type.map(MatchType::as_str)),
+        ]
+            .into_iter()
+            .find_map(|(index, value)| value.map(|v| (index, v)))
+    }
+
+    /// The walk a page is read from (without its cursor), and the name cursors are tied to.
+    fn scan(&self) -> (OrderScan, String) {
+        let (index, descending, sort) = self.sort.unwrap_or_default().scan();
+        let walk = |index, (start, end), sort| {
+            (OrderScan { index, descending, after: None, start, end, limit: page_limit(self.limit) }, sort)
+        };
+        // Keys are `{value}\0{id}`, so one value's entries sit between `{value}` and `{value}\x01`
+        let prefix = |value: &str| (Some(value.to_string()), Some(format!("{}\u{1}", value)));
+
+        match (index, self.order_id.as_deref(), self.filter_index()) {
+            (OrderIndex::Date, _, _) => {
+                let range = (
+                    self.date_from.map(|d| d.to_string()),
+                    self.date_to.and_then(|d| d.succ_opt()).map(|d| d.to_string()),
+                );
+                walk(index, range, sort.to_string())
+            }
+            (_, Some(order_id), _) => walk(index, prefix(order_id), sort.to_string()),
+            // The filter's own index replaces the order_id one; cursors are tied to the filter value
+            (_, None, Some((filter, value))) => {
+                let sort = format!("{}{}={}", if descending { "-" } else { "" }, filter.db_name(), value);
+                walk(filter, prefix(value), sort)
+            }
+            (_, None, None) => walk(index, (None, None), sort.to_string()),
+        }
+    }
+
+    fn matches(&self, order: &Order) -> bool {
+        let exact = |filter: &Option<String>, value: Option<&str>| {
+            filter.as_deref().is_none_or(|f| value == Some(f))
+        };
+        // Dates may carry a time; the day is the first ten characters
+        let day = order.date.get(..10).unwrap_or(&order.date);
+
+        exact(&self.marketplace, Some(&order.marketplace)) &&
+            self.status.is_none_or(|s| order.status == Some(s)) &&
+            self.match_type.is_none_or(|m| order.match_type == Some(m)) &&
+            exact(&self.order_id, Some(&order.order_id)) &&
+            self.date_from.is_none_or(|from| day >= from.to_string().as_str()) &&
+            self.date_to.is_none_or(|to| day <= to.to_string().as_str())
     }
 }
 
-/// List all Orders
+/// List Orders
+///
+/// Filters are combined with AND. Pages are read in `sort` order straight from
+/// the order_id or date index; a date sort also limits the walk to the date
+/// range, and an order_id sort to the `order_id` filter. Otherwise, with a
+/// `marketplace`, `status` or `match_type` filter (the first one set) the walk
+/// covers only that filter's orders, read from its index in order id order,
+/// reversed for a `-` sort.
 #[utoipa::path(
     get,
     path = "/orders",
+    params(OrderQuery),
     responses(
-        (status = 200, description = "List all orders", body = [Order]),
-        (status = 500, description = "List error")
+        (status = 200, description = "One page of orders", body = Page<Order>),
+        (status = 400, description = "Invalid cursor", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "List error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn list_orders(db: web::Data<DB>) -> impl Responder {
-    match db.get() {
-        Ok(Some(orders)) => HttpResponse::Ok().json(orders),
-        Ok(None) => HttpResponse::Ok().json(Vec::<Order>::new()), // empty list instead of None
-        Err(e) => HttpResponse::InternalServerError().body(format!("List error: {}", e)),
-    }
+pub async fn list_orders(db: web::Data<DB>, query: web::Query<OrderQuery>) -> Result<HttpResponse, ApiError> {
+    let (mut scan, sort) = query.scan();
+    scan.after = query.cursor
+        .as_deref()
+        .map(|c| decode_cursor(&sort, c))
+        .transpose()
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, "invalid_cursor", e))?;
+
+    let (items, last_key) = db.page_orders(&scan, &|order| query.matches(order))?;
+    let next_cursor = last_key.map(|key| encode_cursor(&sort, &key));
+    Ok(HttpResponse::Ok().json(Page { items, next_cursor }))
 }
 
 /// Update an existing Order
+///
+/// Changed fields that a sync can also write are marked as API edits, so a
+/// sheet edit to the same field before the next sync becomes a conflict.
+/// The status can't be changed here; use `POST /orders/{id}/transition`.
+/// The fields a PATCH can't change keep their stored values, and `updated_at`
+/// moves when anything changed. `If-Match` must carry the ETag the change is based on; new orders are
+/// created with `POST /orders`. The sheets are updated in the background
+/// once the order is stored, and a failed write is retried on the next sync.
 #[utoipa::path(
     put,
     path = "/orders",
     request_body = Order,
+    params(
+        ("If-Match" = String, Header, description = "ETag of the revision being replaced, or `*`")
+    ),
     responses(
-        (status = 200, description = "Order updated"),
-        (status = 500, description = "Update error")
+        (status = 200, description = "Order updated, sheet write-back started", headers(("ETag" = String, description = "The order's new revision"))),
+        (status = 400, description = "Malformed JSON body or If-Match", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Order not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The order changed since that ETag", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Fields failed validation, or the status was changed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Update error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn update_order(db: web::Data<DB>, item: web::Json<Order>) -> impl Responder {
-    let order = item.into_inner();
-
-    // 1. Pehle DB me Order update kar
-    if let Err(e) = db.put(order.clone()) {
-        return HttpResponse::InternalServerError()
-            .body(format!("DB update failed: {}", e));
-    }
+pub async fn update_order(
+    db: web::Data<DB>,
+    writer: web::Data<SheetWriter>,
+    actor: Actor,
+    precondition: Precondition,
+    item: ValidatedJson<Order>
+) -> Result<HttpResponse, ApiError> {
+    let if_match = precondition.required()?;
+    let mut update = item.into_inner();
+    let id = update.id.clone();
 
-    // // 2. Order id se sheets ke existing rows fetch kar
-    // // Assume order_id field unique id ke liye hai, agar tera alag id hai to use kar
-    // let order_id = order.order_id.as_str();
+    // 1. Pehle DB me Order update kar; revision aur status isi transaction me check hote hain
+    let now = Utc::now().to_rfc3339();
+    let order = db.modify(&id, &actor.0, Some(if_match), |order| {
+        check_status_unchanged(order, &update)?;
+        // Same rules as READ_ONLY_FIELDS for PATCH: whatever the body says, these stay
+        update.source = order.source.clone();
+        update.created_at = order.created_at.clone();
+        update.field_meta = order.field_meta.clone();
+        update.status_history = order.status_history.clone();
+        update.updated_at = order.updated_at.clone();
+        update.revision = order.revision;
+        track_api_edit(order, &mut update, &now);
+        if fields_differ(order, &update) {
+            update.updated_at = now.clone();
+        }
+        *order = update;
+        Ok::<_, ApiError>(())
+    })?;
 
-    // let mut sheet1_row = match db.get_sheet1_row(order_id).await {
-    //     Some(row) => row,
-    //     None => vec!["".to_string(); 15],
-    // };
-
-    // let mut sheet2_row = match db.get_sheet2_row(order_id).await {
-    //     Some(row) => row,
-    //     None => vec!["".to_string(); 15],
-    // };
-
-    // // 3. Update rows based on order fields
-    // update_rows_for_order(&order, &mut sheet1_row, &mut sheet2_row);
+    // 2. Sheet1/Sheet2 rows background me patch honge; fail hua to retry queue me chala jayega
+    writer.spawn_write(order.id.clone());
+    Ok(HttpResponse::Ok().insert_header(etag(order.revision)).body("Order updated successfully"))
+}
 
-    // // 4. Save updated rows back
-    // if let Err(e) = db.save_sheet1_row(order_id, sheet1_row).await {
-    //     return HttpResponse::InternalServerError()
-    //         .body(format!("Sheet1 update failed: {}", e));
-    // }
-    // if let Err(e) = db.save_sheet2_row(order_id, sheet2_row).await {
-    //     return HttpResponse::InternalServerError()
-    //         .body(format!("Sheet2 update failed: {}", e));
-    // }
+/// Change some fields of an Order
+///
+/// The body is a JSON Merge Patch (RFC 7396): only the fields it names change,
+/// and `null` clears an optional field. The patched order is validated like a
+/// full one, `updated_at` moves when anything changed, and it is written back
+/// to the sheets like a `PUT`. The status can't be patched; use
+/// `POST /orders/{id}/transition`.
+#[utoipa::path(
+    patch,
+    path = "/orders/{id}",
+    params(
+        ("id" = String, Path, description = "Order ID"),
+        ("If-Match" = String, Header, description = "ETag of the revision being patched, or `*`")
+    ),
+    request_body(
+        content = Order,
+        content_type = "application/merge-patch+json",
+        description = "Only the fields to change",
+        example = json!({ "qty": 2, "matched_sku": null })
+    ),
+    responses(
+        (status = 200, description = "Order patched, sheet write-back started", body = Order, headers(("ETag" = String, description = "The order's new revision"))),
+        (status = 400, description = "Malformed patch or If-Match", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Order not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The order changed since that ETag", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "The patch isn't JSON", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The patched order failed validation, or a read-only field or the status was changed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Update error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn patch_order(
+    db: web::Data<DB>,
+    writer: web::Data<SheetWriter>,
+    actor: Actor,
+    precondition: Precondition,
+    path: web::Path<String>,
+    patch: MergePatch
+) -> Result<HttpResponse, ApiError> {
+    let if_match = precondition.required()?;
+    let now = Utc::now().to_rfc3339();
+    // Applied to the stored order inside the write transaction
+    let order = db.modify(&path.into_inner(), &actor.0, Some(if_match), |order| {
+        let mut patched = apply_patch(order, &patch, READ_ONLY_FIELDS)?;
+        check_status_unchanged(order, &patched)?;
+        track_api_edit(order, &mut patched, &now);
+        if fields_differ(order, &patched) {
+            patched.updated_at = now.clone();
+        }
+        *order = patched;
+        Ok::<_, ApiError>(())
+    })?;
 
-    HttpResponse::Ok().body("Order and sheets updated successfully")
+    writer.spawn_write(order.id.clone());
+    Ok(HttpResponse::Ok().insert_header(etag(order.revision)).json(order))
 }
 
 /// Delete an Order by id
+///
+/// `If-Match` must carry the ETag of the revision being deleted.
 #[utoipa::path(
     delete,
     path = "/orders/{id}",
     params(
-        ("id" = String, Path, description = "Order ID")
+        ("id" = String, Path, description = "Order ID"),
+        ("If-Match" = String, Header, description = "ETag of the revision being deleted, or `*`")
     ),
     responses(
         (status = 200, description = "Order deleted"),
-        (status = 500, description = "Delete error")
+        (status = 400, description = "Malformed If-Match", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Order not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The order changed since that ETag", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Delete error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn delete_order(db: web::Data<DB>, path: web::Path<String>) -> impl Responder {
-    match db.delete(path.into_inner()) {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Delete error: {}", e)),
-    }
+pub async fn delete_order(
+    db: web::Data<DB>,
+    actor: Actor,
+    precondition: Precondition,
+    path: web::Path<String>
+) -> Result<HttpResponse, ApiError> {
+    db.delete(path.into_inner(), &actor.0, Some(precondition.required()?))?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Browse the change history of an Order
+///
+/// Every create, update and delete, newest first, with who made it and the
+/// fields that changed. Sheet imports are recorded with the actor `sync`.
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/history",
+    params(
+        ("id" = String, Path, description = "Order ID"),
+        HistoryQuery
+    ),
+    responses(
+        (status = 200, description = "One page of changes", body = Page<AuditEntry>),
+        (status = 400, description = "Invalid cursor", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No history for this order", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "History error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_order_history(
+    db: web::Data<DB>,
+    path: web::Path<String>,
+    query: web::Query<HistoryQuery>
+) -> Result<HttpResponse, ApiError> {
+    history_page(&db, "order", &path.into_inner(), &query)
+}
+
+/// Move an Order to another status
+///
+/// Allowed moves: none → received → inspected → matched → refunded or
+/// restocked. The move and its actor (the `x-actor` header) are appended to
+/// `status_history`, and the new status is written back to the sheets like
+/// any other update. An `If-Match` is optional here, since the move is
+/// checked against the current status anyway.
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/transition",
+    params(
+        ("id" = String, Path, description = "Order ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag the move is based on, or `*`")
+    ),
+    request_body = TransitionRequest,
+    responses(
+        (status = 200, description = "Status changed, sheet write-back started", body = Order, headers(("ETag" = String, description = "The order's new revision"))),
+        (status = 400, description = "Malformed If-Match", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Order not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The move isn't allowed from the current status", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The order changed since that ETag", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Fields failed validation", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Update error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn transition_order(
+    db: web::Data<DB>,
+    writer: web::Data<SheetWriter>,
+    actor: Actor,
+    path: web::Path<String>,
+    precondition: Precondition,
+    item: ValidatedJson<TransitionRequest>
+) -> Result<HttpResponse, ApiError> {
+    let request = item.into_inner();
+    let now = Utc::now().to_rfc3339();
+    // Checked and stored in one transaction so concurrent moves can't both pass
+    let order = db.modify(&path.into_inner(), &actor.0, precondition.0.as_ref(), |order| {
+        let before = order.clone();
+        order
+            .transition(request.to, &actor.0, request.note, &now)
+            .map_err(|e| ApiError::conflict("illegal_transition", e.to_string()))?;
+        track_api_edit(&before, order, &now);
+        Ok::<_, ApiError>(())
+    })?;
+
+    writer.spawn_write(order.id.clone());
+    Ok(HttpResponse::Ok().insert_header(etag(order.revision)).json(order))
 }
 
 /// Configure routes for orders
@@ -135,6 +461,204 @@ pub fn order_config(cfg: &mut web::ServiceConfig) {
     .service(
         web::resource("/orders/{id}")
             .route(web::get().to(get_order))
+            .route(web::patch().to(patch_order))
             .route(web::delete().to(delete_order)),
-    );
+    )
+    .service(web::resource("/orders/{id}/transition").route(web::post().to(transition_order)))
+    .service(web::resource("/orders/{id}/history").route(web::get().to(get_order_history)));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{middleware, App, ResponseError};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::lmdb::{utils::init_db, writeback::DBWriteback};
+    use crate::routes::error::extractor_config;
+    use crate::routes::request_id::request_id;
+    use crate::scripts::google_sheet_order::sync_sheets;
+    use crate::scripts::mock_sheets::MockSheets;
+    use crate::scripts::sheets_client::SheetsClient;
+    use crate::scripts::token::TokenProvider;
+
+    fn query(q: &str) -> OrderQuery {
+        web::Query::<OrderQuery>::from_query(q).unwrap().into_inner()
+    }
+
+    #[test]
+    fn exact_filters_bound_the_walk() {
+        let (scan, sort) = query("status=received").scan();
+        assert_eq!(scan.index, OrderIndex::Status);
+        assert_eq!((scan.start.as_deref(), scan.end.as_deref()), (Some("received"), Some("received\u{1}")));
+        assert_eq!(sort, "orders_by_status=received");
+
+        // The marketplace filter wins over the status one
+        let (scan, sort) = query("status=received&marketplace=shop&sort=-order_id").scan();
+        assert_eq!(scan.index, OrderIndex::Marketplace);
+        assert!(scan.descending);
+        assert_eq!(sort, "-orders_by_marketplace=shop");
+
+        // An order_id filter keeps the order_id sort and bounds that index instead
+        let (scan, sort) = query("order_id=42&status=received").scan();
+        assert_eq!(scan.index, OrderIndex::OrderId);
+        assert_eq!(scan.start.as_deref(), Some("42"));
+        assert_eq!(sort, "order_id");
+
+        // A date sort keeps its index and range
+        let (scan, sort) = query("status=received&sort=date&date_from=2024-01-01&date_to=2024-01-31").scan();
+        assert_eq!(scan.index, OrderIndex::Date);
+        assert_eq!((scan.start.as_deref(), scan.end.as_deref()), (Some("2024-01-01"), Some("2024-02-01")));
+        assert_eq!(sort, "date");
+
+        let (scan, _) = query("").scan();
+        assert_eq!((scan.index, scan.start), (OrderIndex::OrderId, None));
+    }
+
+    fn patch(fields: Value) -> MergePatch {
+        let Value::Object(fields) = fields else { panic!("not an object") };
+        MergePatch(fields)
+    }
+
+    #[test]
+    fn patch_keeps_read_only_fields() {
+        let order = Order { source: Some("amazon-eu".to_string()), revision: 3, ..Order::sample("1") };
+
+        let patched = apply_patch(&order, &patch(json!({"marketplace": "amazon", "qty": 2})), READ_ONLY_FIELDS).unwrap();
+        assert_eq!((patched.marketplace.as_str(), patched.qty), ("amazon", Some(2)));
+
+        // Sending a read-only field back unchanged is fine
+        let patched = apply_patch(&order, &patch(json!({"source": "amazon-eu", "revision": 3})), READ_ONLY_FIELDS).unwrap();
+        assert_eq!(patched, order);
+
+        for fields in [json!({"source": "other"}), json!({"source": null}), json!({"id": "2"}), json!({"revision": 9})] {
+            let err = apply_patch(&order, &patch(fields.clone()), READ_ONLY_FIELDS).unwrap_err();
+            assert_eq!(err.status_code(), StatusCode::UNPROCESSABLE_ENTITY, "patch {}", fields);
+        }
+    }
+
+    fn grid(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|c| c.to_string()).collect())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn patch_writes_the_changed_cells_back_to_the_mock_sheets() {
+        let dir = tempfile::tempdir().unwrap();
+        let mock = MockSheets::default();
+        mock.set_tab("sp", "Sheet1", grid(&[
+            &["MARTKETPLACE", "BIN_RACK", "ORDER_ID", "DATE", "NOTES"],
+            &["amazon", "B-1", "1001", "2023-11-05", "fragile"],
+            &["amazon", "B-2", "1002", "2023-11-06", ""],
+        ]));
+        mock.set_tab("sp", "Sheet2", grid(&[
+            &["RETURN_ORDER", "MARKETPLACE", "QTY", "NOTES"],
+            &["1002", "amazon", "1", "x"],
+            &["1001", "amazon", "2", "keep me"],
+        ]));
+        let settings = Arc::new(mock.start("sp", dir.path()).unwrap());
+
+        let db = init_db(&settings.lmdb_path).await.unwrap();
+        let http = SheetsClient::http_client(settings.http_timeout);
+        let tokens = TokenProvider::new(&settings.service_account, http.clone());
+        let sheets = SheetsClient::new(&settings, http, tokens);
+        sync_sheets(&settings, &sheets, &db, false, None).await.unwrap();
+        let imported = db.get_single("1001".to_string()).unwrap().expect("imported");
+
+        let writer = SheetWriter::new(settings.clone(), sheets, db.clone());
+        let app = init_service(
+            App::new()
+                .wrap(middleware::from_fn(request_id))
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(writer))
+                .configure(extractor_config)
+                .configure(order_config)
+        ).await;
+
+        let req = TestRequest::patch()
+            .uri("/orders/1001")
+            .insert_header(("content-type", "application/merge-patch+json"))
+            .insert_header(("if-match", format!("\"{}\"", imported.revision)))
+            .set_payload(r#"{"marketplace": "ebay", "qty": 3}"#)
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // The write-back runs after the response
+        let mut sheet2 = Vec::new();
+        for _ in 0..250 {
+            sheet2 = mock.tab("sp", "Sheet2").unwrap();
+            if sheet2[2][2] == "3" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(sheet2, grid(&[
+            &["RETURN_ORDER", "MARKETPLACE", "QTY", "NOTES"],
+            &["1002", "amazon", "1", "x"],
+            &["1001", "ebay", "3", "keep me"],
+        ]));
+        // DATE keeps the sheet's date, not the day the order was imported
+        assert_eq!(mock.tab("sp", "Sheet1").unwrap(), grid(&[
+            &["MARTKETPLACE", "BIN_RACK", "ORDER_ID", "DATE", "NOTES"],
+            &["ebay", "B-1", "1001", "2023-11-05", "fragile"],
+            &["amazon", "B-2", "1002", "2023-11-06", ""],
+        ]));
+        assert!(db.pending_writebacks().unwrap().is_empty());
+
+        // A stale If-Match is refused and nothing more is written
+        let req = TestRequest::patch()
+            .uri("/orders/1001")
+            .insert_header(("content-type", "application/merge-patch+json"))
+            .insert_header(("if-match", format!("\"{}\"", imported.revision)))
+            .set_payload(r#"{"marketplace": "shopify"}"#)
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[actix_web::test]
+    async fn put_keeps_the_read_only_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = Arc::new(MockSheets::default().start("sp", dir.path()).unwrap());
+        let db = init_db(&settings.lmdb_path).await.unwrap();
+        let http = SheetsClient::http_client(settings.http_timeout);
+        let tokens = TokenProvider::new(&settings.service_account, http.clone());
+        let writer = SheetWriter::new(settings.clone(), SheetsClient::new(&settings, http, tokens), db.clone());
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(writer))
+                .configure(extractor_config)
+                .configure(order_config)
+        ).await;
+
+        let stored = Order { source: Some("amazon-eu".to_string()), ..Order::sample("1") };
+        let revision = db.insert(stored.clone(), "test").unwrap();
+
+        let body = Order {
+            marketplace: "ebay".to_string(),
+            source: Some("other".to_string()),
+            created_at: "2030-01-01T00:00:00Z".to_string(),
+            updated_at: "2030-01-01T00:00:00Z".to_string(),
+            ..stored.clone()
+        };
+        let req = TestRequest::put()
+            .uri("/orders")
+            .insert_header(("if-match", format!("\"{}\"", revision)))
+            .set_json(&body)
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+
+        let order = db.get_single("1".to_string()).unwrap().unwrap();
+        assert_eq!(order.marketplace, "ebay");
+        assert_eq!(order.source.as_deref(), Some("amazon-eu"));
+        assert_eq!(order.created_at, stored.created_at);
+        assert_ne!(order.updated_at, body.updated_at);
+        assert_ne!(order.updated_at, stored.updated_at);
+    }
 }
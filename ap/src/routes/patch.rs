@@ -0,0 +1,125 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::{ dev::Payload, http::StatusCode, web, FromRequest, HttpMessage, HttpRequest };
+use serde::{ de::DeserializeOwned, Serialize };
+use serde_json::{ Map, Value };
+use validator::Validate;
+
+use crate::routes::error::{ ApiError, FieldError };
+use crate::routes::extract::check_valid;
+
+/// An RFC 7396 JSON Merge Patch body, sent as `application/merge-patch+json`
+/// (plain `application/json` is accepted too). It must be a JSON object.
+#[derive(Debug)]
+pub struct MergePatch(pub Map<String, Value>);
+
+impl FromRequest for MergePatch {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let content_type = req.content_type().to_string();
+        let body = web::Bytes::from_request(req, payload);
+        Box::pin(async move {
+            if content_type != "application/merge-patch+json" && content_type != "application/json" {
+                return Err(
+                    ApiError::new(
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        "unsupported_media_type",
+                        "Send the patch as application/merge-patch+json"
+                    ).into()
+                );
+            }
+            let body = body.await?;
+            match serde_json::from_slice(&body) {
+                Ok(Value::Object(fields)) => Ok(MergePatch(fields)),
+                Ok(_) => Err(ApiError::new(StatusCode::BAD_REQUEST, "invalid_body", "The patch must be a JSON object").into()),
+                Err(e) => Err(ApiError::new(StatusCode::BAD_REQUEST, "invalid_body", e.to_string()).into()),
+            }
+        })
+    }
+}
+
+/// RFC 7396: objects merge member by member, `null` removes a member, and
+/// anything else replaces the target's value.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(members) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(fields) = target {
+        for (name, value) in members {
+            if value.is_null() {
+                fields.remove(name);
+            } else {
+                merge_patch(fields.entry(name.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// Returns `record` with `patch` applied, after the same checks a full body
+/// gets. The `read_only` fields can't be changed, only sent back as they are;
+/// a removed optional field becomes `None`.
+pub fn apply_patch<T>(record: &T, patch: &MergePatch, read_only: &[&str]) -> Result<T, ApiError>
+    where T: Serialize + DeserializeOwned + Validate
+{
+    let before = serde_json::to_value(record).map_err(|e| {
+        tracing::error!("Failed to encode record for patching: {}", e);
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "encode_error", "Failed to encode the record")
+    })?;
+    let mut after = before.clone();
+    merge_patch(&mut after, &Value::Object(patch.0.clone()));
+
+    let locked: Vec<FieldError> = read_only
+        .iter()
+        .filter(|field| before.get(**field) != after.get(**field))
+        .map(|field| FieldError { field: field.to_string(), message: "is read-only".to_string() })
+        .collect();
+    if !locked.is_empty() {
+        return Err(ApiError::validation(locked));
+    }
+
+    let patched: T = serde_json::from_value(after).map_err(|e| {
+        ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "invalid_patch", e.to_string())
+    })?;
+    check_valid(&patched)?;
+    Ok(patched)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn merge_patch_follows_rfc_7396() {
+        // Examples from RFC 7396 appendix A
+        let cases = [
+            (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "b"}), json!({"b": "c"}), json!({"a": "b", "b": "c"})),
+            (json!({"a": "b"}), json!({"a": null}), json!({})),
+            (json!({"a": "b", "b": "c"}), json!({"a": null}), json!({"b": "c"})),
+            (json!({"a": ["b"]}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "c"}), json!({"a": ["b"]}), json!({"a": ["b"]})),
+            (json!({"a": {"b": "c"}}), json!({"a": {"b": "d", "c": null}}), json!({"a": {"b": "d"}})),
+            (json!({"a": [{"b": "c"}]}), json!({"a": [1]}), json!({"a": [1]})),
+            (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+            (json!({"a": "b"}), json!(["c"]), json!(["c"])),
+            (json!({"a": "foo"}), json!(null), json!(null)),
+            (json!({"a": "foo"}), json!("bar"), json!("bar")),
+            (json!({"e": null}), json!({"a": 1}), json!({"e": null, "a": 1})),
+            (json!([1, 2]), json!({"a": "b", "c": null}), json!({"a": "b"})),
+            (json!({}), json!({"a": {"bb": {"ccc": null}}}), json!({"a": {"bb": {}}})),
+        ];
+        for (mut target, patch, expected) in cases {
+            merge_patch(&mut target, &patch);
+            assert_eq!(target, expected, "patch {}", patch);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::future::{ ready, Ready };
+
+use actix_web::{ dev::Payload, http::header::{ self, EntityTag, Header }, http::StatusCode, FromRequest, HttpRequest };
+
+use crate::lmdb::revision::IfMatch;
+use crate::routes::error::ApiError;
+
+/// The ETag of a record at `revision`: the revision as a strong tag, `"3"`.
+pub fn etag(revision: u64) -> header::ETag {
+    header::ETag(EntityTag::new_strong(revision.to_string()))
+}
+
+/// The request's `If-Match` header, if it sent one. Weak and foreign tags
+/// never match a revision; a header that doesn't parse is a 400.
+#[derive(Debug, Clone)]
+pub struct Precondition(pub Option<IfMatch>);
+
+impl Precondition {
+    /// Updates and deletes must say which revision they were based on; a 428 otherwise.
+    pub fn required(&self) -> Result<&IfMatch, ApiError> {
+        self.0.as_ref().ok_or_else(|| {
+            ApiError::new(
+                StatusCode::PRECONDITION_REQUIRED,
+                "if_match_required",
+                "Send If-Match with the ETag the change is based on"
+            )
+        })
+    }
+}
+
+impl FromRequest for Precondition {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        if !req.headers().contains_key(header::IF_MATCH) {
+            return ready(Ok(Precondition(None)));
+        }
+        let condition = match header::IfMatch::parse(req) {
+            Ok(header::IfMatch::Any) => IfMatch::Any,
+            Ok(header::IfMatch::Items(tags)) if !tags.is_empty() => {
+                IfMatch::Revisions(
+                    tags
+                        .iter()
+                        .filter(|t| !t.weak)
+                        .filter_map(|t| t.tag().parse().ok())
+                        .collect()
+                )
+            }
+            _ => {
+                return ready(
+                    Err(ApiError::new(StatusCode::BAD_REQUEST, "invalid_if_match", "If-Match must be * or quoted ETags"))
+                );
+            }
+        };
+        ready(Ok(Precondition(Some(condition))))
+    }
+}
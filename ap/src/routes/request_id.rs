@@ -0,0 +1,44 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ ServiceRequest, ServiceResponse };
+use actix_web::http::header::{ HeaderName, HeaderValue };
+use actix_web::middleware::Next;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, if called from inside [`request_id`].
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// A caller-supplied id is kept when it is short and printable.
+fn incoming_id(req: &ServiceRequest) -> Option<String> {
+    let id = req.headers().get(&REQUEST_ID_HEADER)?.to_str().ok()?;
+    let usable = !id.is_empty() && id.len() <= 128 && id.chars().all(|c| c.is_ascii_graphic());
+    usable.then(|| id.to_string())
+}
+
+/// Middleware giving every request an id: available to handlers through
+/// [`current`], echoed in the `x-request-id` response header and logged with
+/// server errors.
+pub async fn request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let id = incoming_id(&req).unwrap_or_else(|| Uuid::new_v4().to_string());
+    let method = req.method().clone();
+    let path = req.path().to_string();
+
+    let mut res = REQUEST_ID.scope(id.clone(), next.call(req)).await?;
+    if res.status().is_server_error() {
+        tracing::error!("{} {} failed with {} (request {})", method, path, res.status(), id);
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(res)
+}
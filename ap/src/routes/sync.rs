@@ -0,0 +1,177 @@
+use actix_web::{ http::StatusCode, web, HttpResponse, Responder };
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::lmdb::target::DBSyncTarget;
+use crate::lmdb::utils::DB;
+use crate::routes::error::{ ApiError, ProblemDetails };
+use crate::routes::request_id;
+use crate::schema::sync::SyncReport;
+use crate::scripts::scheduler::{ SyncJob, SyncStatus };
+use crate::scripts::targets::DEFAULT_TARGET;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TargetQuery {
+    /// Only this sync target instead of every enabled one
+    target: Option<String>,
+}
+
+/// Preview a sheet sync
+///
+/// Reads the sheets and reports the inserts, field-level updates and skipped
+/// rows a sync would produce. Nothing is written to the orders database.
+/// Why reading the sheets failed is logged under the request id, not returned.
+#[utoipa::path(
+    get,
+    path = "/sync/dry-run",
+    params(TargetQuery),
+    responses(
+        (status = 200, description = "Changes the next sync would make", body = SyncReport),
+        (status = 404, description = "Sync target not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 502, description = "Reading the sheets failed", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn dry_run_sync(
+    db: web::Data<DB>,
+    job: web::Data<SyncJob>,
+    query: web::Query<TargetQuery>
+) -> Result<HttpResponse, ApiError> {
+    if let Some(id) = query.target.as_deref() && id != DEFAULT_TARGET && db.get_target(id)?.is_none() {
+        return Err(ApiError::not_found("Sync target not found"));
+    }
+
+    match job.dry_run(query.target.as_deref()).await {
+        Ok(report) => Ok(HttpResponse::Ok().json(report)),
+        Err(e) => {
+            tracing::error!("Dry run failed (request {}): {}", request_id::current().unwrap_or_default(), e);
+            Err(ApiError::new(StatusCode::BAD_GATEWAY, "sheets_unavailable", "Reading the sheets failed"))
+        }
+    }
+}
+
+/// Get sheet sync status
+///
+/// When the last run started and finished and how long it took, and for each
+/// sync target the per-source row counts of its last successful run and the
+/// error of its last failed one, if any.
+#[utoipa::path(
+    get,
+    path = "/sync/status",
+    responses(
+        (status = 200, description = "Current sync status", body = SyncStatus)
+    )
+)]
+pub async fn sync_status(job: web::Data<SyncJob>) -> impl Responder {
+    HttpResponse::Ok().json(job.status().await)
+}
+
+/// Start a sheet sync of every enabled target now
+///
+/// The run happens in the background; poll `/sync/status` for the result.
+/// Refused while another run, scheduled or manual, is still going.
+#[utoipa::path(
+    post,
+    path = "/sync/run",
+    responses(
+        (status = 202, description = "Sync started"),
+        (status = 409, description = "A sync is already running", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn run_sync(job: web::Data<SyncJob>) -> Result<HttpResponse, ApiError> {
+    if job.trigger(None) {
+        Ok(HttpResponse::Accepted().body("Sync started"))
+    } else {
+        Err(ApiError::conflict("sync_running", "A sync is already running"))
+    }
+}
+
+/// Configure routes for sheet sync
+pub fn sync_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/sync/dry-run").route(web::get().to(dry_run_sync)))
+        .service(web::resource("/sync/status").route(web::get().to(sync_status)))
+        .service(web::resource("/sync/run").route(web::post().to(run_sync)));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use actix_web::{ middleware, test, App };
+    use serde_json::Value;
+
+    use super::*;
+    use crate::lmdb::order::DBOrder;
+    use crate::lmdb::utils::init_db;
+    use crate::routes::error::extractor_config;
+    use crate::routes::request_id::request_id;
+    use crate::schema::order::ReturnStatus;
+    use crate::scripts::mock_sheets::MockSheets;
+    use crate::scripts::sheet_writeback::SheetWriter;
+    use crate::scripts::sheets_client::SheetsClient;
+    use crate::scripts::token::TokenProvider;
+
+    fn grid(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|c| c.to_string()).collect())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn run_imports_the_mock_sheets() {
+        let dir = tempfile::tempdir().unwrap();
+        let mock = MockSheets::default();
+        mock.set_tab("sp", "Sheet1", grid(&[
+            &["MARTKETPLACE", "ORDER_ID"],
+            &["amazon", "1001"],
+            &["", "1002"],
+        ]));
+        mock.set_tab("sp", "Sheet2", grid(&[
+            &["RETURN_ORDER", "STATUS", "QTY"],
+            &["1001", "received", "2"],
+        ]));
+        let settings = Arc::new(mock.start("sp", dir.path()).unwrap());
+
+        let db = init_db(&settings.lmdb_path).await.unwrap();
+        let http = SheetsClient::http_client(settings.http_timeout);
+        let tokens = TokenProvider::new(&settings.service_account, http.clone());
+        let sheets = SheetsClient::new(&settings, http, tokens);
+        let writer = SheetWriter::new(settings.clone(), sheets.clone(), db.clone());
+        let job = SyncJob::new(settings.clone(), sheets, db.clone(), writer);
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware::from_fn(request_id))
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(job))
+                .configure(extractor_config)
+                .configure(sync_config)
+        ).await;
+
+        // A dry run reports the insert without storing it
+        let report: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/sync/dry-run").to_request()).await;
+        assert_eq!(report["plan"]["dry_run"], true);
+        assert_eq!(report["plan"]["inserts"][0]["order_id"], "1001");
+        assert_eq!(report["plan"]["skipped"][0]["row"], 3);
+        assert_eq!(db.get_single("1001".to_string()).unwrap(), None);
+
+        let res = test::call_service(&app, test::TestRequest::post().uri("/sync/run").to_request()).await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        let mut status: Value = Value::Null;
+        for _ in 0..250 {
+            status = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/sync/status").to_request()).await;
+            if status["runs"] == 1 && status["running"] == false {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(status["runs"], 1, "sync didn't finish: {}", status);
+        assert_eq!(status["last_run_error"], Value::Null);
+        assert_eq!(status["targets"]["default"]["last_error"], Value::Null);
+
+        let order = db.get_single("1001".to_string()).unwrap().expect("imported");
+        assert_eq!((order.marketplace.as_str(), order.source.as_deref()), ("amazon", Some("default")));
+        assert_eq!((order.status, order.qty), (Some(ReturnStatus::Received), Some(2)));
+        // No marketplace and no target default: skipped, not stored
+        assert_eq!(db.get_single("1002".to_string()).unwrap(), None);
+    }
+}
@@ -0,0 +1,168 @@
+use actix_web::{ web, HttpResponse };
+use chrono::Utc;
+
+use crate::lmdb::target::DBSyncTarget;
+use crate::lmdb::utils::DB;
+use crate::routes::error::{ ApiError, ProblemDetails };
+use crate::routes::extract::{ check_valid, ValidatedJson };
+use crate::schema::sync_target::SyncTarget;
+use crate::scripts::scheduler::SyncJob;
+use crate::scripts::targets::DEFAULT_TARGET;
+
+/// Register a sync target
+#[utoipa::path(
+    post,
+    path = "/sync/targets",
+    request_body = SyncTarget,
+    responses(
+        (status = 201, description = "Sync target created", body = SyncTarget),
+        (status = 422, description = "Invalid target", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "A target with this id already exists", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Insert error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn create_target(db: web::Data<DB>, item: ValidatedJson<SyncTarget>) -> Result<HttpResponse, ApiError> {
+    let mut target = item.into_inner();
+    let now = Utc::now().to_rfc3339();
+    target.created_at = now.clone();
+    target.updated_at = now;
+    db.insert_target(&target)?;
+    Ok(HttpResponse::Created().json(target))
+}
+
+/// List stored sync targets
+///
+/// The `default` target built from the environment settings is not listed.
+#[utoipa::path(
+    get,
+    path = "/sync/targets",
+    responses(
+        (status = 200, description = "All stored sync targets", body = [SyncTarget]),
+        (status = 500, description = "List error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn list_targets(db: web::Data<DB>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(db.list_targets()?))
+}
+
+/// Get a sync target by id
+#[utoipa::path(
+    get,
+    path = "/sync/targets/{id}",
+    params(
+        ("id" = String, Path, description = "Sync target ID")
+    ),
+    responses(
+        (status = 200, description = "Sync target found", body = SyncTarget),
+        (status = 404, description = "Sync target not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Get error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_target(db: web::Data<DB>, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    match db.get_target(&path.into_inner())? {
+        Some(target) => Ok(HttpResponse::Ok().json(target)),
+        None => Err(ApiError::not_found("Sync target not found")),
+    }
+}
+
+/// Replace a sync target
+///
+/// The id in the path wins over the one in the body.
+#[utoipa::path(
+    put,
+    path = "/sync/targets/{id}",
+    params(
+        ("id" = String, Path, description = "Sync target ID")
+    ),
+    request_body = SyncTarget,
+    responses(
+        (status = 200, description = "Sync target updated", body = SyncTarget),
+        (status = 422, description = "Invalid target", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Sync target not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Update error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn update_target(
+    db: web::Data<DB>,
+    path: web::Path<String>,
+    item: web::Json<SyncTarget>
+) -> Result<HttpResponse, ApiError> {
+    let mut target = item.into_inner();
+    target.id = path.into_inner();
+    check_valid(&target)?;
+
+    target.updated_at = Utc::now().to_rfc3339();
+    let target = db.update_target(&target)?;
+    Ok(HttpResponse::Ok().json(target))
+}
+
+/// Delete a sync target
+///
+/// Orders it imported keep their `source` tag; they are no longer synced or written back.
+#[utoipa::path(
+    delete,
+    path = "/sync/targets/{id}",
+    params(
+        ("id" = String, Path, description = "Sync target ID")
+    ),
+    responses(
+        (status = 200, description = "Sync target deleted"),
+        (status = 404, description = "Sync target not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Delete error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn delete_target(db: web::Data<DB>, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    if db.delete_target(&path.into_inner())? {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Err(ApiError::not_found("Sync target not found"))
+    }
+}
+
+/// Re-sync a single target now
+///
+/// Only orders tagged with this target are touched. Works for disabled targets
+/// and for `default`. Refused while another run is still going.
+#[utoipa::path(
+    post,
+    path = "/sync/targets/{id}/run",
+    params(
+        ("id" = String, Path, description = "Sync target ID, or `default`")
+    ),
+    responses(
+        (status = 202, description = "Sync started"),
+        (status = 404, description = "Sync target not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "A sync is already running", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Get error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn run_target(db: web::Data<DB>, job: web::Data<SyncJob>, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    if id != DEFAULT_TARGET && db.get_target(&id)?.is_none() {
+        return Err(ApiError::not_found("Sync target not found"));
+    }
+
+    if job.trigger(Some(id)) {
+        Ok(HttpResponse::Accepted().body("Sync started"))
+    } else {
+        Err(ApiError::conflict("sync_running", "A sync is already running"))
+    }
+}
+
+/// Configure routes for sync targets
+pub fn sync_target_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web
+            ::resource("/sync/targets")
+            .route(web::post().to(create_target))
+            .route(web::get().to(list_targets))
+    )
+        .service(
+            web
+                ::resource("/sync/targets/{id}")
+                .route(web::get().to(get_target))
+                .route(web::put().to(update_target))
+                .route(web::delete().to(delete_target))
+        )
+        .service(web::resource("/sync/targets/{id}/run").route(web::post().to(run_target)));
+}
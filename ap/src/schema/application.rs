@@ -1,8 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use validator::Validate;
 
-#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, Validate, Clone, PartialEq)]
 #[schema(example = json!({
     "id": "550e8400-e29b-41d4-a716-446655440000",
     "permit_number": "PERMIT-12345",
@@ -12,34 +13,48 @@ use utoipa::ToSchema;
     "receipt_no": 987654,
     "address": "123 Main St, City",
     "version": "v1.0.0",
+    "revision": 3,
     "created_at": "2023-01-01T00:00:00Z",
     "updated_at": "2023-01-01T00:00:00Z"
 }))]
 pub struct Application {
-    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000", min_length = 1)]
+    #[validate(length(min = 1))]
     pub id: String,
 
-    #[schema(example = "PERMIT-12345")]
+    #[schema(example = "PERMIT-12345", min_length = 1, max_length = 40)]
+    #[validate(length(min = 1, max = 40))]
     pub permit_number: String,
 
-    #[schema(example = 1234)]
+    #[schema(example = 1234, minimum = 0, maximum = 9999)]
+    #[validate(range(min = 0, max = 9999))]
     pub card_ending: i64,
 
-    #[schema(example = 100.0)]
+    #[schema(example = 100.0, minimum = 0)]
+    #[validate(range(min = 0.0))]
     pub total_paid: f64,
 
     #[schema(example = "2023-01-01T00:00:00Z")]
     pub date: DateTime<Utc>,
 
-    #[schema(example = 987654)]
+    #[schema(example = 987654, minimum = 0)]
+    #[validate(range(min = 0))]
     pub receipt_no: i64,
 
-    #[schema(example = "123 Main St, City")]
+    #[schema(example = "123 Main St, City", max_length = 200)]
+    #[validate(length(max = 200))]
     pub address: Option<String>,
 
-    #[schema(example = "v1.0.0")]
+    #[schema(example = "v1.0.0", max_length = 20)]
+    #[validate(length(max = 20))]
     pub version: String,
 
+    /// Bumped on every write; sent as the ETag and expected back in `If-Match`.
+    /// Maintained by the server; ignored in request bodies.
+    #[serde(default)]
+    #[schema(read_only, example = 3)]
+    pub revision: u64,
+
     #[schema(example = "2023-01-01T00:00:00Z")]
     pub created_at: String,
 
@@ -0,0 +1,32 @@
+use serde::{ Deserialize, Serialize };
+use utoipa::ToSchema;
+
+use crate::schema::sync::FieldChange;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOp {
+    Create,
+    Update,
+    Delete,
+}
+
+/// One change to a stored record. Entries are only ever appended.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Position in the record's history, starting at 1
+    #[schema(example = 3)]
+    pub seq: u64,
+    #[schema(example = "order")]
+    pub entity: String,
+    #[schema(example = "1234567890")]
+    pub record_id: String,
+    pub op: AuditOp,
+    /// `x-actor` of the request, `sync` for sheet imports
+    #[schema(example = "jane@warehouse")]
+    pub actor: String,
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub at: String,
+    /// Top-level fields that changed; every field for creates and deletes
+    pub changes: Vec<FieldChange>,
+}
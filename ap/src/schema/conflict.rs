@@ -0,0 +1,51 @@
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// An order field that was edited in the sheet and through the API since the
+/// last sync. The sync leaves the field alone until the conflict is resolved.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct SyncConflict {
+    /// `{order id}:{field}`; there is at most one open conflict per field.
+    #[schema(example = "1234567890:status")]
+    pub id: String,
+    #[schema(example = "1234567890")]
+    pub order_id: String,
+    #[schema(example = "status")]
+    pub field: String,
+    /// Sync target whose sheet holds the conflicting row.
+    #[schema(example = "default")]
+    pub target: String,
+    /// Value at the last sync, `null` when unknown.
+    #[schema(value_type = Object, example = "pending")]
+    pub base: Value,
+    #[schema(value_type = Object, example = "inspected")]
+    pub sheet_value: Value,
+    #[schema(value_type = Object, example = "rejected")]
+    pub api_value: Value,
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub api_modified_at: String,
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub detected_at: String,
+}
+
+/// Which value a conflict resolves to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// Keep the sheet's value.
+    Sheet,
+    /// Keep the order's current value and write it to the sheet.
+    Api,
+    /// Use `value` and write it to the sheet.
+    Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct ResolveConflict {
+    pub resolution: Resolution,
+    /// Required when `resolution` is `value`.
+    #[serde(default)]
+    #[schema(value_type = Object, example = "inspected")]
+    pub value: Option<Value>,
+}
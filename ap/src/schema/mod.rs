@@ -1,3 +1,8 @@
 pub mod application;
+pub mod audit;
+pub mod conflict;
 pub mod user;
-pub mod order;
\ No newline at end of file
+pub mod order;
+pub mod page;
+pub mod sync;
+pub mod sync_target;
\ No newline at end of file
@@ -1,13 +1,220 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use validator::Validate;
+
+/// Where a returned item is in its handling. Moves only along [`TRANSITIONS`].
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnStatus {
+    Received,
+    Inspected,
+    Matched,
+    Refunded,
+    Restocked,
+}
+
+/// The allowed status moves; `None` is an order that has no status yet.
+pub const TRANSITIONS: &[(Option<ReturnStatus>, ReturnStatus)] = &[
+    (None, ReturnStatus::Received),
+    (Some(ReturnStatus::Received), ReturnStatus::Inspected),
+    (Some(ReturnStatus::Inspected), ReturnStatus::Matched),
+    (Some(ReturnStatus::Matched), ReturnStatus::Refunded),
+    (Some(ReturnStatus::Matched), ReturnStatus::Restocked),
+];
+
+impl ReturnStatus {
+    pub const ALL: [ReturnStatus; 5] = [
+        ReturnStatus::Received,
+        ReturnStatus::Inspected,
+        ReturnStatus::Matched,
+        ReturnStatus::Refunded,
+        ReturnStatus::Restocked,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReturnStatus::Received => "received",
+            ReturnStatus::Inspected => "inspected",
+            ReturnStatus::Matched => "matched",
+            ReturnStatus::Refunded => "refunded",
+            ReturnStatus::Restocked => "restocked",
+        }
+    }
+
+    /// Whether an order in status `from` may move to `to`.
+    pub fn can_transition(from: Option<ReturnStatus>, to: ReturnStatus) -> bool {
+        TRANSITIONS.contains(&(from, to))
+    }
+}
+
+/// How a return was matched to its order.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchType {
+    Automatic,
+    Manual,
+    Unmatched,
+}
+
+impl MatchType {
+    pub const ALL: [MatchType; 3] = [MatchType::Automatic, MatchType::Manual, MatchType::Unmatched];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatchType::Automatic => "automatic",
+            MatchType::Manual => "manual",
+            MatchType::Unmatched => "unmatched",
+        }
+    }
+}
+
+/// Whether someone confirmed a manual match (the sheets' REFUND_YES column).
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Confirmation {
+    Pending,
+    Confirmed,
+    Rejected,
+}
+
+impl Confirmation {
+    pub const ALL: [Confirmation; 3] = [Confirmation::Pending, Confirmation::Confirmed, Confirmation::Rejected];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Confirmation::Pending => "pending",
+            Confirmation::Confirmed => "confirmed",
+            Confirmation::Rejected => "rejected",
+        }
+    }
+}
 
+/// Reads a sheet cell leniently: case, surrounding blanks and `-`/space for `_`
+/// don't matter. Confirmations also take yes/no.
+pub trait SheetLabel: Sized + Copy + 'static {
+    const KIND: &'static str;
+
+    fn all() -> &'static [Self];
+    fn label(self) -> &'static str;
+
+    fn aliases(_cell: &str) -> Option<Self> {
+        None
+    }
+
+    fn from_cell(cell: &str) -> Result<Self, String> {
+        let cell = cell.trim().to_ascii_lowercase().replace(['-', ' '], "_");
+        Self::all()
+            .iter()
+            .copied()
+            .find(|v| v.label() == cell)
+            .or_else(|| Self::aliases(&cell))
+            .ok_or_else(|| format!("not a valid {}", Self::KIND))
+    }
+}
+
+impl SheetLabel for ReturnStatus {
+    const KIND: &'static str = "status";
+
+    fn all() -> &'static [Self] {
+        &Self::ALL
+    }
+
+    fn label(self) -> &'static str {
+        self.as_str()
+    }
+}
+
+impl SheetLabel for MatchType {
+    const KIND: &'static str = "match type";
+
+    fn all() -> &'static [Self] {
+        &Self::ALL
+    }
+
+    fn label(self) -> &'static str {
+        self.as_str()
+    }
+}
+
+impl SheetLabel for Confirmation {
+    const KIND: &'static str = "confirmation";
+
+    fn all() -> &'static [Self] {
+        &Self::ALL
+    }
+
+    fn label(self) -> &'static str {
+        self.as_str()
+    }
+
+    fn aliases(cell: &str) -> Option<Self> {
+        match cell {
+            "yes" | "y" | "true" => Some(Confirmation::Confirmed),
+            "no" | "n" | "false" => Some(Confirmation::Rejected),
+            _ => None,
+        }
+    }
+}
+
+/// One status move, made through the API, a sheet import or a conflict resolution.
 #[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq)]
+pub struct StatusChange {
+    pub from: Option<ReturnStatus>,
+    pub to: ReturnStatus,
+    #[schema(example = "jane@warehouse")]
+    pub actor: String,
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub at: String,
+    pub note: Option<String>,
+}
+
+/// Body of `POST /orders/{id}/transition`.
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct TransitionRequest {
+    pub to: ReturnStatus,
+    #[schema(example = "Box opened, item intact", max_length = 500)]
+    #[validate(length(max = 500))]
+    pub note: Option<String>,
+}
+
+/// A status move not in [`TRANSITIONS`].
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("can't move an order from {} to {}", .from.map_or("no status", |s| s.as_str()), .to.as_str())]
+pub struct IllegalTransition {
+    pub from: Option<ReturnStatus>,
+    pub to: ReturnStatus,
+}
+
+/// Which side last wrote an order field.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldOrigin {
+    Sheet,
+    Api,
+}
+
+/// Where an order field's current value came from and when it was set.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq)]
+pub struct FieldMeta {
+    pub origin: FieldOrigin,
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub modified_at: String,
+    /// JSON of the value the sheet held at the last sync. A sheet value that
+    /// differs from it was edited in the sheet since.
+    #[schema(example = "\"inspected\"")]
+    pub synced: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Validate, Clone, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub struct Order {
     #[schema(
         value_type = String,
         example = "550e8400-e29b-41d4-a716-446655440000"
     )]
+    #[validate(length(min = 1))]
     pub id: String,
 
     #[schema(
@@ -15,56 +222,83 @@ pub struct Order {
         max_length = 20,
         pattern = r"^[a-zA-Z0-9_\-]+$"
     )]
+    #[validate(length(max = 20), regex(path = *MARKETPLACE_REGEX))]
     pub marketplace: String,
 
-    #[schema(example = "1234567890", max_length = 20)]
+    #[schema(example = "1234567890", min_length = 1, max_length = 20)]
+    #[validate(length(min = 1, max = 20))]
     pub order_id: String,
 
     #[schema(example = "1234567890", max_length = 20)]
     pub return_order: Option<u64>,
 
     #[schema(example = "1234567890", max_length = 20)]
+    #[validate(length(max = 20))]
     pub shopify_id: Option<String>,
 
     #[schema(example = "1234567890", max_length = 20)]
+    #[validate(length(max = 20))]
     pub market_place_code: Option<String>,
 
     #[schema(example = "1234567890", max_length = 20)]
+    #[validate(length(max = 20))]
     pub returned_sku: Option<String>,
 
     #[schema(example = "1234567890", max_length = 20)]
+    #[validate(length(max = 20))]
     pub offer_sku: Option<String>,
 
     #[schema(example = "1234567890", max_length = 20)]
+    #[validate(length(max = 20))]
     pub matched_sku: Option<String>,
 
-    #[schema(
-        example = "automatic",
-        max_length = 20
-    )]
-    pub match_type: Option<String>,
+    #[schema(example = "automatic")]
+    pub match_type: Option<MatchType>,
 
     #[schema(example = "1", maximum = 9999999)]
+    #[validate(range(max = 9999999))]
     pub row_number: Option<u32>,
 
-    #[schema(
-        example = "confirmed",
-        max_length = 20,
-    )]
-    pub manual_confirmation: Option<String>,
+    #[schema(example = "confirmed")]
+    pub manual_confirmation: Option<Confirmation>,
 
-    #[schema(
-        example = "processed",
-        max_length = 20,
-    )]
-    pub status: Option<String>,
+    /// Changed through `POST /orders/{id}/transition`, not by updating the order.
+    #[schema(example = "received")]
+    pub status: Option<ReturnStatus>,
 
     #[schema(example = "1", maximum = 999)]
+    #[validate(range(max = 999))]
     pub qty: Option<u32>,
 
-    #[schema(example = "true", max_length = 5)]
+    /// `SYNCED` once the sync has merged the Sheet2 row in.
+    #[schema(example = "SYNCED", max_length = 10)]
+    #[validate(length(max = 10))]
     pub main_updated: Option<String>,
 
+    /// Sync target the order was imported from; `None` for orders created through the API.
+    #[serde(default)]
+    #[schema(example = "default", max_length = 40)]
+    #[validate(length(max = 40))]
+    pub source: Option<String>,
+
+    /// Provenance of the fields a sync can write, keyed by field name.
+    /// Maintained by the server; ignored in request bodies.
+    #[serde(default)]
+    #[schema(read_only)]
+    pub field_meta: BTreeMap<String, FieldMeta>,
+
+    /// Status moves made through the API, oldest first. Maintained by the
+    /// server; ignored in request bodies.
+    #[serde(default)]
+    #[schema(read_only)]
+    pub status_history: Vec<StatusChange>,
+
+    /// Bumped on every write; sent as the ETag and expected back in `If-Match`.
+    /// Maintained by the server; ignored in request bodies.
+    #[serde(default)]
+    #[schema(read_only, example = 3)]
+    pub revision: u64,
+
     #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
     pub date: String,
 
@@ -75,8 +309,164 @@ pub struct Order {
     pub updated_at: String,
 }
 
+impl Order {
+    /// Moves the order to `to` if [`TRANSITIONS`] allows it, recording who did it.
+    pub fn transition(
+        &mut self,
+        to: ReturnStatus,
+        actor: &str,
+        note: Option<String>,
+        at: &str
+    ) -> Result<(), IllegalTransition> {
+        let from = self.status;
+        if !ReturnStatus::can_transition(from, to) {
+            return Err(IllegalTransition { from, to });
+        }
+        self.status = Some(to);
+        self.status_history.push(StatusChange { from, to, actor: actor.to_string(), at: at.to_string(), note });
+        self.updated_at = at.to_string();
+        Ok(())
+    }
+
+    /// Puts a status set directly on a copy of `before`, by a sheet import or
+    /// a conflict resolution, through [`Order::transition`], so it follows
+    /// [`TRANSITIONS`] and lands in `status_history`. A move that isn't allowed
+    /// puts the status and its provenance back to `before`'s.
+    pub fn confirm_status_change(
+        &mut self,
+        before: &Order,
+        actor: &str,
+        note: Option<String>,
+        at: &str
+    ) -> Result<(), IllegalTransition> {
+        let Some(to) = self.status.filter(|_| self.status != before.status) else {
+            return Ok(());
+        };
+        let meta = self.field_meta.remove("status");
+        self.status = before.status;
+        if let Some(before_meta) = before.field_meta.get("status") {
+            self.field_meta.insert("status".to_string(), before_meta.clone());
+        }
+        self.transition(to, actor, note, at)?;
+        if let Some(meta) = meta {
+            self.field_meta.insert("status".to_string(), meta);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Order {
+    /// A valid order with only the required fields set, for tests.
+    pub fn sample(id: &str) -> Order {
+        Order {
+            id: id.to_string(),
+            marketplace: "shopify".to_string(),
+            order_id: id.to_string(),
+            return_order: None,
+            shopify_id: None,
+            market_place_code: None,
+            returned_sku: None,
+            offer_sku: None,
+            matched_sku: None,
+            match_type: None,
+            row_number: None,
+            manual_confirmation: None,
+            status: None,
+            qty: None,
+            main_updated: None,
+            source: None,
+            field_meta: BTreeMap::new(),
+            status_history: Vec::new(),
+            revision: 0,
+            date: "2024-01-01".to_string(),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            updated_at: "2024-01-01T00:00:00Z".to_string(),
+        }
+    }
+}
+
 
 lazy_static::lazy_static! {
-    static ref MARKETPLACE_REGEX: regex::Regex = 
+    pub(crate) static ref MARKETPLACE_REGEX: regex::Regex = 
         regex::Regex::new(r"^[a-zA-Z0-9_\-]+$").unwrap();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet_meta(synced: &str) -> FieldMeta {
+        FieldMeta {
+            origin: FieldOrigin::Sheet,
+            modified_at: "2024-01-02T00:00:00Z".to_string(),
+            synced: Some(synced.to_string()),
+        }
+    }
+
+    #[test]
+    fn transitions_only_follow_the_table() {
+        for from in std::iter::once(None).chain(ReturnStatus::ALL.map(Some)) {
+            for to in ReturnStatus::ALL {
+                let mut order = Order { status: from, ..Order::sample("1") };
+                let result = order.transition(to, "alice", None, "2024-01-02T00:00:00Z");
+                if TRANSITIONS.contains(&(from, to)) {
+                    assert_eq!(result, Ok(()));
+                    assert_eq!(order.status, Some(to));
+                } else {
+                    assert_eq!(result, Err(IllegalTransition { from, to }));
+                    assert_eq!(order.status, from);
+                    assert!(order.status_history.is_empty());
+                }
+            }
+        }
+        // Refunded and restocked are final
+        assert!(!ReturnStatus::can_transition(Some(ReturnStatus::Refunded), ReturnStatus::Restocked));
+        assert!(!ReturnStatus::can_transition(Some(ReturnStatus::Received), ReturnStatus::Received));
+    }
+
+    #[test]
+    fn transition_records_who_moved_the_order() {
+        let mut order = Order::sample("1");
+        order.transition(ReturnStatus::Received, "alice", None, "2024-01-02T00:00:00Z").unwrap();
+        order.transition(ReturnStatus::Inspected, "bob", Some("box dented".to_string()), "2024-01-03T00:00:00Z").unwrap();
+
+        assert_eq!(order.status_history, [
+            StatusChange {
+                from: None,
+                to: ReturnStatus::Received,
+                actor: "alice".to_string(),
+                at: "2024-01-02T00:00:00Z".to_string(),
+                note: None,
+            },
+            StatusChange {
+                from: Some(ReturnStatus::Received),
+                to: ReturnStatus::Inspected,
+                actor: "bob".to_string(),
+                at: "2024-01-03T00:00:00Z".to_string(),
+                note: Some("box dented".to_string()),
+            },
+        ]);
+        assert_eq!(order.updated_at, "2024-01-03T00:00:00Z");
+    }
+
+    #[test]
+    fn direct_status_changes_follow_the_transitions() {
+        let before = Order { status: Some(ReturnStatus::Received), ..Order::sample("1") };
+
+        let mut allowed = Order { status: Some(ReturnStatus::Inspected), ..before.clone() };
+        allowed.field_meta.insert("status".to_string(), sheet_meta("\"inspected\""));
+        allowed.confirm_status_change(&before, "sync", None, "2024-01-02T00:00:00Z").unwrap();
+        assert_eq!(allowed.status, Some(ReturnStatus::Inspected));
+        assert_eq!(allowed.status_history.len(), 1);
+        assert_eq!(allowed.status_history[0].from, Some(ReturnStatus::Received));
+        assert_eq!(allowed.field_meta["status"], sheet_meta("\"inspected\""));
+
+        let mut refused = Order { status: Some(ReturnStatus::Refunded), ..before.clone() };
+        refused.field_meta.insert("status".to_string(), sheet_meta("\"refunded\""));
+        assert!(refused.confirm_status_change(&before, "sync", None, "2024-01-02T00:00:00Z").is_err());
+        assert_eq!(refused.status, Some(ReturnStatus::Received));
+        assert!(refused.status_history.is_empty());
+        assert!(!refused.field_meta.contains_key("status"));
+    }
+}
@@ -0,0 +1,84 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+pub const DEFAULT_LIMIT: usize = 50;
+pub const MAX_LIMIT: usize = 500;
+
+/// One page of a list endpoint. Pass `next_cursor` back as `cursor` (with the
+/// same `sort`) for the following page; it is `null` on the last one.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    #[schema(example = "6f726465725f6964")]
+    pub next_cursor: Option<String>,
+}
+
+/// Makes an opaque cursor from the sort it belongs to and the storage key of
+/// the last item on the page.
+pub fn encode_cursor(sort: &str, key: &str) -> String {
+    format!("{}\n{}", sort, key)
+        .bytes()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The storage key in `cursor`. Fails on anything [`encode_cursor`] didn't
+/// produce for `sort`.
+pub fn decode_cursor(sort: &str, cursor: &str) -> Result<String, String> {
+    let invalid = || "Invalid cursor".to_string();
+    if !cursor.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| cursor.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    let text = String::from_utf8(bytes).map_err(|_| invalid())?;
+
+    match text.split_once('\n') {
+        Some((cursor_sort, key)) if cursor_sort == sort => Ok(key.to_string()),
+        Some(_) => Err("Cursor belongs to a different sort order".to_string()),
+        None => Err(invalid()),
+    }
+}
+
+/// `limit` clamped to 1..=[`MAX_LIMIT`].
+pub fn page_limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips_for_its_sort_only() {
+        let cursor = encode_cursor("-orders_by_status=received", "received\u{0}42");
+        assert!(cursor.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(decode_cursor("-orders_by_status=received", &cursor).as_deref(), Ok("received\u{0}42"));
+        assert_eq!(
+            decode_cursor("orders_by_status=received", &cursor),
+            Err("Cursor belongs to a different sort order".to_string())
+        );
+
+        // An empty key is still a key
+        assert_eq!(decode_cursor("order_id", &encode_cursor("order_id", "")).as_deref(), Ok(""));
+    }
+
+    #[test]
+    fn rejects_cursors_it_did_not_make() {
+        let hex_without_separator: String = "order_id".bytes().map(|b| format!("{:02x}", b)).collect();
+        for cursor in ["abc", "zz", "ff", "\u{e9}0", hex_without_separator.as_str()] {
+            assert_eq!(decode_cursor("order_id", cursor), Err("Invalid cursor".to_string()), "cursor {:?}", cursor);
+        }
+    }
+
+    #[test]
+    fn limit_is_clamped() {
+        assert_eq!(page_limit(None), DEFAULT_LIMIT);
+        assert_eq!(page_limit(Some(0)), 1);
+        assert_eq!(page_limit(Some(20)), 20);
+        assert_eq!(page_limit(Some(10_000)), MAX_LIMIT);
+    }
+}
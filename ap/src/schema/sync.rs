@@ -0,0 +1,130 @@
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::schema::conflict::SyncConflict;
+use crate::schema::order::Order;
+
+/// An order whose sheet write-back failed and is waiting to be retried.
+/// The order itself is re-read from the `orders` database on retry, so the
+/// latest version is what ends up in the sheet.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PendingWriteback {
+    pub id: String,
+    pub attempts: u32,
+    pub last_error: String,
+    pub queued_at: String,
+    pub last_attempt_at: String,
+}
+
+/// A sheet row the importer could not use. `row` is the 1-based sheet row.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct RowIssue {
+    pub row: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct Sheet1ImportReport {
+    pub source: String,
+    pub rows: usize,
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    /// Fields left alone because they were also edited through the API.
+    pub conflicts: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct Sheet2ImportReport {
+    pub rows: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub conflicts: usize,
+    pub unmatched: Vec<RowIssue>,
+    pub invalid: Vec<RowIssue>,
+}
+
+/// What a sync run did for one target.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct TargetSummary {
+    #[schema(example = "default")]
+    pub target: String,
+    /// The live Sheet1 tab first, then any files from the import directory.
+    pub sheet1: Vec<Sheet1ImportReport>,
+    pub sheet2: Sheet2ImportReport,
+}
+
+/// What a single sheet sync run did.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct SyncSummary {
+    pub targets: Vec<TargetSummary>,
+    /// Totals across all targets. Rows touched by both tabs count once per tab.
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub conflicts: usize,
+}
+
+impl SyncSummary {
+    pub fn new(targets: Vec<TargetSummary>) -> Self {
+        let (mut inserted, mut updated, mut unchanged, mut conflicts) = (0, 0, 0, 0);
+        for target in &targets {
+            for report in &target.sheet1 {
+                inserted += report.inserted;
+                updated += report.updated;
+                unchanged += report.unchanged;
+                conflicts += report.conflicts;
+            }
+            updated += target.sheet2.updated;
+            unchanged += target.sheet2.unchanged;
+            conflicts += target.sheet2.conflicts;
+        }
+        Self { targets, inserted, updated, unchanged, conflicts }
+    }
+}
+
+/// One field that differs between the stored order and the sheet.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct FieldChange {
+    #[schema(example = "status")]
+    pub field: String,
+    #[schema(value_type = Object, example = json!(null))]
+    pub before: Value,
+    #[schema(value_type = Object, example = "inspected")]
+    pub after: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct OrderUpdate {
+    #[schema(example = "1234567890")]
+    pub id: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// A source row that produced no change, with the reason.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct SkippedRow {
+    #[schema(example = "Sheet2")]
+    pub source: String,
+    #[schema(example = 12)]
+    pub row: usize,
+    pub reason: String,
+}
+
+/// Per-order changes a sync run makes (or, for a dry run, would make) to the `orders` database.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, ToSchema)]
+pub struct SyncPlan {
+    pub dry_run: bool,
+    pub inserts: Vec<Order>,
+    pub updates: Vec<OrderUpdate>,
+    pub skipped: Vec<SkippedRow>,
+    /// Conflicts this run queued, or whose sheet value changed since they were queued.
+    pub conflicts: Vec<SyncConflict>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, ToSchema)]
+pub struct SyncReport {
+    pub summary: SyncSummary,
+    pub plan: SyncPlan,
+}
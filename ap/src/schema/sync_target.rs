@@ -0,0 +1,98 @@
+use serde::{ Deserialize, Serialize };
+use utoipa::ToSchema;
+use validator::{ Validate, ValidationError };
+
+use crate::config::columns::{ ColumnAliases, ColumnOverrides };
+use crate::schema::order::MARKETPLACE_REGEX;
+use crate::scripts::targets::DEFAULT_TARGET;
+
+/// A spreadsheet the sync imports from, in addition to the one configured
+/// through the environment (target `default`).
+///
+/// The id doubles as the `source` tag on every order the target imports.
+/// Validation checks what the importer would otherwise only find out mid-sync.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Validate, Clone, PartialEq, Eq)]
+pub struct SyncTarget {
+    #[validate(length(min = 1, max = 40), regex(path = *TARGET_ID_REGEX), custom(function = "not_default_target"))]
+    #[schema(example = "amazon-eu", max_length = 40, pattern = r"^[a-zA-Z0-9_\-]+$")]
+    pub id: String,
+
+    #[schema(example = "Amazon EU returns")]
+    pub name: String,
+
+    #[validate(custom(function = "not_blank"))]
+    #[schema(example = "1BxiMVs0XRA5nFMdKvBdBZjgmUUqptlbs74OgvE2upms")]
+    pub spreadsheet_id: String,
+
+    #[serde(default = "default_sheet1_name")]
+    #[validate(custom(function = "not_blank"))]
+    #[schema(example = "Sheet1")]
+    pub sheet1_name: String,
+
+    #[serde(default = "default_sheet2_name")]
+    #[validate(custom(function = "not_blank"))]
+    #[schema(example = "Sheet2")]
+    pub sheet2_name: String,
+
+    /// Marketplace recorded for rows whose MARKETPLACE cell is blank.
+    #[serde(default)]
+    #[validate(length(max = 20), regex(path = *MARKETPLACE_REGEX))]
+    #[schema(example = "amazon")]
+    pub marketplace: Option<String>,
+
+    /// Header aliases replacing the defaults for this target's tabs.
+    #[serde(default)]
+    #[validate(custom(function = "valid_overrides"))]
+    pub columns: ColumnOverrides,
+
+    /// Disabled targets are skipped by the scheduled sync but can still be run by hand.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    #[serde(default)]
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub created_at: String,
+
+    #[serde(default)]
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub updated_at: String,
+}
+
+fn default_sheet1_name() -> String {
+    "Sheet1".to_string()
+}
+
+fn default_sheet2_name() -> String {
+    "Sheet2".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(ValidationError::new("blank").with_message("can't be empty".into()));
+    }
+    Ok(())
+}
+
+fn not_default_target(id: &str) -> Result<(), ValidationError> {
+    if id == DEFAULT_TARGET {
+        let message = format!("{} is the target configured through the environment", DEFAULT_TARGET);
+        return Err(ValidationError::new("reserved").with_message(message.into()));
+    }
+    Ok(())
+}
+
+fn valid_overrides(overrides: &ColumnOverrides) -> Result<(), ValidationError> {
+    ColumnAliases::default()
+        .with_overrides(overrides)
+        .map(|_| ())
+        .map_err(|e| ValidationError::new("columns").with_message(e.into()))
+}
+
+lazy_static::lazy_static! {
+    static ref TARGET_ID_REGEX: regex::Regex =
+        regex::Regex::new(r"^[a-zA-Z0-9_\-]+$").unwrap();
+}
@@ -3,6 +3,7 @@ use serde::{ Deserialize, Serialize };
 use utoipa::{ ToSchema };
 use secrecy::SecretString;
 use uuid::Uuid;
+use validator::Validate;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "snake_case")]
@@ -15,46 +16,53 @@ pub enum UserRole {
     Guest,
 }
 
+/// A user account. Stored through [`DBUser`](crate::lmdb::user::DBUser).
 #[allow(dead_code)]
-#[derive(Debug, Clone, ToSchema, Serialize)]
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct User {
     #[schema(example = "550e8400-e29b-41d4-a716-446655440000", value_type = String)]
-    id: Uuid,
+    pub id: Uuid,
 
     #[schema(example = "john_doe", pattern = r"^[a-zA-Z0-9_]{3,30}$", max_length = 30)]
-    username: String,
+    pub username: String,
 
     #[schema(example = "john@example.com", pattern = r"^[^@\s]+@[^@\s]+\.[^@\s]+$")]
-    email: String,
+    pub email: String,
 
     #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
-    created_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
 
     #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
-    updated_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 
+    /// Left out whenever a user is serialized, so it can't end up in a
+    /// response or the audit history; the user table's codec is the one place
+    /// that writes it.
     #[schema(ignore)] // Never expose password in API docs
     #[serde(skip_serializing)]
-    password_hash: SecretString,
+    pub password_hash: SecretString,
 
     #[schema(example = "user")]
-    role: UserRole,
+    pub role: UserRole,
 
     #[schema(example = "v1.0.0", max_length = 20)]
-    version: String,
+    pub version: String,
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, ToSchema, Deserialize)]
+#[derive(Debug, Clone, ToSchema, Deserialize, Validate)]
 pub struct CreateUserRequest {
-    #[schema(example = "john_doe")]
+    #[schema(example = "john_doe", pattern = r"^[a-zA-Z0-9_]{3,30}$", max_length = 30)]
+    #[validate(regex(path = *USERNAME_REGEX))]
     pub username: String,
 
-    #[schema(example = "john@example.com")]
+    #[schema(example = "john@example.com", pattern = r"^[^@\s]+@[^@\s]+\.[^@\s]+$")]
+    #[validate(email)]
     pub email: String,
 
-    #[schema(example = "strongPassword123!")]
+    #[schema(example = "strongPassword123!", min_length = 8, max_length = 128)]
+    #[validate(length(min = 8, max = 128))]
     pub password: String,
 
     #[schema(default = "UserRole::User")]
@@ -62,14 +70,22 @@ pub struct CreateUserRequest {
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, ToSchema, Deserialize)]
+#[derive(Debug, Clone, ToSchema, Deserialize, Validate)]
 pub struct UpdateUserRequest {
-    #[schema(example = "new_username")]
+    #[schema(example = "new_username", pattern = r"^[a-zA-Z0-9_]{3,30}$", max_length = 30)]
+    #[validate(regex(path = *USERNAME_REGEX))]
     pub username: Option<String>,
 
-    #[schema(example = "new@example.com")]
+    #[schema(example = "new@example.com", pattern = r"^[^@\s]+@[^@\s]+\.[^@\s]+$")]
+    #[validate(email)]
     pub email: Option<String>,
 
-    #[schema(example = "newStrongPassword123!")]
+    #[schema(example = "newStrongPassword123!", min_length = 8, max_length = 128)]
+    #[validate(length(min = 8, max = 128))]
     pub password: Option<String>,
 }
+
+lazy_static::lazy_static! {
+    static ref USERNAME_REGEX: regex::Regex =
+        regex::Regex::new(r"^[a-zA-Z0-9_]{3,30}$").unwrap();
+}
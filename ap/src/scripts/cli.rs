@@ -0,0 +1,113 @@
+use std::io;
+
+use crate::config::columns::{ ColumnAliases, ColumnSpec };
+use crate::config::settings::Settings;
+use crate::lmdb::index::DBOrderIndex;
+use crate::lmdb::utils::DB;
+use crate::scripts::google_sheet_order::sync_sheets;
+use crate::scripts::mock_sheets::MockSheets;
+use crate::scripts::sheets_client::SheetsClient;
+
+const USAGE: &str =
+    "usage: production_grade [sync [--dry-run] [--target ID] | reindex | mock-sheets [--bind HOST:PORT] [--data FILE]]";
+
+/// Commands that run without the app settings, so they work on a machine with no credentials.
+pub fn needs_settings(args: &[String]) -> bool {
+    args.first().map(String::as_str) != Some("mock-sheets")
+}
+
+/// Runs a one-off command instead of the server, e.g. `production_grade sync --dry-run`.
+pub async fn run(args: &[String], settings: &Settings, sheets: &SheetsClient, db: &DB) -> io::Result<()> {
+    match args.first().map(String::as_str) {
+        Some("sync") => {
+            let (dry_run, target) = sync_flags(&args[1..])?;
+            let report = sync_sheets(settings, sheets, db, dry_run, target).await.map_err(|e|
+                io::Error::other(e.to_string())
+            )?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            Ok(())
+        }
+        // Fills the secondary indexes for orders written before they existed
+        Some("reindex") if args.len() == 1 => {
+            let count = db.rebuild_order_indexes().map_err(|e| io::Error::other(e.to_string()))?;
+            println!("Reindexed {} orders", count);
+            Ok(())
+        }
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    }
+}
+
+/// `sync`'s flags: whether it is a dry run, and the one target to sync. A
+/// `--target` with no id is an error rather than a sync of every target.
+fn sync_flags(args: &[String]) -> io::Result<(bool, Option<&str>)> {
+    let mut dry_run = false;
+    let mut target = None;
+    let mut flags = args.iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--dry-run" => dry_run = true,
+            "--target" => {
+                let id = flags
+                    .next()
+                    .filter(|id| !id.starts_with("--"))
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "--target needs a sync target id"))?;
+                target = Some(id.as_str());
+            }
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+            }
+        }
+    }
+    Ok((dry_run, target))
+}
+
+/// `mock-sheets`: serves the in-memory Google token and Sheets endpoints until interrupted.
+///
+/// Without `--data` it starts with one spreadsheet, `mock`, holding empty
+/// Sheet1 and Sheet2 tabs with the default headers.
+pub async fn mock_sheets(args: &[String]) -> io::Result<()> {
+    let mut bind_address = "127.0.0.1:9090".to_string();
+    let mut data = None;
+
+    let mut args = args.iter().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+        match flag.as_str() {
+            "--bind" => bind_address = value.clone(),
+            "--data" => data = Some(value.clone()),
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+            }
+        }
+    }
+
+    let mock = match data {
+        Some(path) => MockSheets::from_file(path)?,
+        None => {
+            let mock = MockSheets::default();
+            let header = |specs: &[ColumnSpec]| vec![specs.iter().map(|s| s.aliases[0].clone()).collect()];
+            let columns = ColumnAliases::default();
+            mock.set_tab("mock", "Sheet1", header(&columns.sheet1));
+            mock.set_tab("mock", "Sheet2", header(&columns.sheet2));
+            mock
+        }
+    };
+    mock.serve(&bind_address).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn target_needs_an_id() {
+        assert_eq!(sync_flags(&args(&["--target", "eu", "--dry-run"])).unwrap(), (true, Some("eu")));
+        assert_eq!(sync_flags(&args(&[])).unwrap(), (false, None));
+        assert!(sync_flags(&args(&["--dry-run", "--target"])).is_err());
+        assert!(sync_flags(&args(&["--target", "--dry-run"])).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use serde_json::{ Map, Value };
+
+use crate::schema::conflict::{ Resolution, SyncConflict };
+use crate::schema::order::{ FieldMeta, FieldOrigin, Order };
+
+/// Order fields that both the sheets and the API write, and so can conflict.
+/// `main_updated` and `row_number` are sync bookkeeping and always follow the sheet.
+pub const TRACKED_FIELDS: &[&str] = &[
+    "marketplace",
+    "return_order",
+    "shopify_id",
+    "returned_sku",
+    "offer_sku",
+    "matched_sku",
+    "match_type",
+    "manual_confirmation",
+    "status",
+    "qty",
+];
+
+/// What a sync does with one field the sheet has a value for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldDecision {
+    /// The sheet wasn't edited since the last sync; any API edit stands.
+    Keep,
+    /// The sheet already holds the order's value, only the sync point moves.
+    MarkSynced,
+    /// Only the sheet was edited: take its value.
+    Take,
+    /// Both sides were edited since `base` (the last synced value, if known).
+    Conflict { base: Option<Value> },
+}
+
+/// Compares the sheet's value against the order's and the value at the last sync.
+/// Without a recorded sync point the sheet wins unless the API edited the field.
+pub fn reconcile_field(meta: Option<&FieldMeta>, current: &Value, sheet: &Value) -> FieldDecision {
+    let base: Option<Value> = meta
+        .and_then(|m| m.synced.as_deref())
+        .and_then(|s| serde_json::from_str(s).ok());
+
+    if base.as_ref() == Some(sheet) {
+        return FieldDecision::Keep;
+    }
+    if sheet == current {
+        return FieldDecision::MarkSynced;
+    }
+    let api_edited = meta.is_some_and(|m| m.origin == FieldOrigin::Api) && base.as_ref() != Some(current);
+    if api_edited { FieldDecision::Conflict { base } } else { FieldDecision::Take }
+}
+
+pub fn conflict_id(order_id: &str, field: &str) -> String {
+    format!("{}:{}", order_id, field)
+}
+
+/// Builds the conflict record for `field`, keeping `detected_at` from `open` when
+/// the conflict was already queued.
+pub fn conflict(
+    order: &Order,
+    field: &str,
+    target: &str,
+    base: Option<Value>,
+    sheet_value: Value,
+    open: Option<&SyncConflict>,
+    now: &str
+) -> SyncConflict {
+    let meta = order.field_meta.get(field);
+    SyncConflict {
+        id: conflict_id(&order.id, field),
+        order_id: order.id.clone(),
+        field: field.to_string(),
+        target: target.to_string(),
+        base: base.or_else(|| open.map(|c| c.base.clone())).unwrap_or(Value::Null),
+        sheet_value,
+        api_value: field_values(order).remove(field).unwrap_or(Value::Null),
+        api_modified_at: meta.map(|m| m.modified_at.clone()).unwrap_or_default(),
+        detected_at: open.map_or_else(|| now.to_string(), |c| c.detected_at.clone()),
+    }
+}
+
+/// The order as a field name -> JSON value map.
+pub fn field_values(order: &Order) -> Map<String, Value> {
+    match serde_json::to_value(order) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// Returns `order` with `values` assigned by field name. Fails when a value
+/// doesn't fit the field's type.
+pub fn with_fields(order: &Order, values: Map<String, Value>) -> Result<Order, serde_json::Error> {
+    let mut fields = field_values(order);
+    fields.extend(values);
+    serde_json::from_value(Value::Object(fields))
+}
+
+/// Records an API edit: every tracked field whose value changed between `before`
+/// and `after` is marked as written by the API at `now`. Provenance sent by the
+/// client is discarded.
+pub fn track_api_edit(before: &Order, after: &mut Order, now: &str) {
+    let (old, new) = (field_values(before), field_values(after));
+    after.field_meta = before.field_meta.clone();
+
+    for field in TRACKED_FIELDS {
+        let (Some(old_value), Some(new_value)) = (old.get(*field), new.get(*field)) else {
+            continue;
+        };
+        if old_value == new_value {
+            continue;
+        }
+        // Without a sync point, assume the sheet still holds the value we replaced
+        let synced = before.field_meta
+            .get(*field)
+            .map(|m| m.synced.clone())
+            .unwrap_or_else(|| Some(old_value.to_string()));
+        after.field_meta.insert(field.to_string(), FieldMeta {
+            origin: FieldOrigin::Api,
+            modified_at: now.to_string(),
+            synced,
+        });
+    }
+}
+
+/// Returns `order` with the conflicting field set to `value`. The sync point
+/// becomes the sheet's value, so the next sync neither re-detects the conflict
+/// nor takes the sheet's value over an `api`/`value` resolution still being
+/// written back.
+pub fn resolve(
+    order: &Order,
+    conflict: &SyncConflict,
+    resolution: Resolution,
+    value: Value,
+    now: &str
+) -> Result<Order, serde_json::Error> {
+    let mut resolved = with_fields(order, Map::from_iter([(conflict.field.clone(), value)]))?;
+    let origin = match resolution {
+        Resolution::Sheet => FieldOrigin::Sheet,
+        Resolution::Api | Resolution::Value => FieldOrigin::Api,
+    };
+    resolved.field_meta.insert(conflict.field.clone(), FieldMeta {
+        origin,
+        modified_at: now.to_string(),
+        synced: Some(conflict.sheet_value.to_string()),
+    });
+    resolved.updated_at = now.to_string();
+    Ok(resolved)
+}
+
+/// Whether two versions of an order differ in anything but provenance.
+pub fn fields_differ(a: &Order, b: &Order) -> bool {
+    let mut a = a.clone();
+    a.field_meta = b.field_meta.clone();
+    a != *b
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn meta(origin: FieldOrigin, synced: Option<Value>) -> FieldMeta {
+        FieldMeta {
+            origin,
+            modified_at: "2024-01-02T00:00:00Z".to_string(),
+            synced: synced.map(|v| v.to_string()),
+        }
+    }
+
+    #[test]
+    fn reconcile_field_compares_against_the_last_sync() {
+        let sheet_synced = meta(FieldOrigin::Sheet, Some(json!("received")));
+        let api_edited = meta(FieldOrigin::Api, Some(json!("received")));
+
+        // Sheet unchanged since the last sync: whatever the order holds stands
+        assert_eq!(reconcile_field(Some(&api_edited), &json!("inspected"), &json!("received")), FieldDecision::Keep);
+        // Both already agree
+        assert_eq!(reconcile_field(Some(&api_edited), &json!("inspected"), &json!("inspected")), FieldDecision::MarkSynced);
+        // Only the sheet moved
+        assert_eq!(reconcile_field(Some(&sheet_synced), &json!("received"), &json!("inspected")), FieldDecision::Take);
+        // The API edit was pushed to the sheet already, so only the sheet moved since
+        let api_written = meta(FieldOrigin::Api, Some(json!("inspected")));
+        assert_eq!(reconcile_field(Some(&api_written), &json!("inspected"), &json!("matched")), FieldDecision::Take);
+        // Both moved
+        assert_eq!(
+            reconcile_field(Some(&api_edited), &json!("inspected"), &json!("matched")),
+            FieldDecision::Conflict { base: Some(json!("received")) }
+        );
+    }
+
+    #[test]
+    fn reconcile_field_without_a_sync_point() {
+        assert_eq!(reconcile_field(None, &json!(1), &json!(2)), FieldDecision::Take);
+        assert_eq!(reconcile_field(None, &json!(2), &json!(2)), FieldDecision::MarkSynced);
+        assert_eq!(reconcile_field(Some(&meta(FieldOrigin::Sheet, None)), &json!(1), &json!(2)), FieldDecision::Take);
+        assert_eq!(
+            reconcile_field(Some(&meta(FieldOrigin::Api, None)), &json!(1), &json!(2)),
+            FieldDecision::Conflict { base: None }
+        );
+    }
+}
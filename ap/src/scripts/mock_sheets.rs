@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{ Arc, RwLock };
+
+use actix_web::{ web, App, HttpRequest, HttpResponse, HttpServer, Responder };
+use serde::Deserialize;
+use serde_json::{ json, Value };
+
+use crate::scripts::source::{ value_rows, Grid };
+#[cfg(test)]
+use crate::config::{ columns::ColumnAliases, settings::{ ServiceAccountKey, Settings, SheetLocation } };
+#[cfg(test)]
+use std::time::Duration;
+
+/// The only bearer token the mock accepts; anything else gets a 401.
+pub const MOCK_ACCESS_TOKEN: &str = "mock-access-token";
+
+/// Path prefix of the mocked values API, so the base URL looks like Google's.
+const API_PREFIX: &str = "/v4/spreadsheets";
+
+/// spreadsheet id -> tab name -> cells
+type Spreadsheets = HashMap<String, HashMap<String, Grid>>;
+
+/// In-memory stand-in for the Google token endpoint and the Sheets values API.
+///
+/// Point `GOOGLE_TOKEN_URI` at `{base}/token` and `SHEETS_API_URL` at
+/// `{base}/v4/spreadsheets` to run the sync and write-back against it. The
+/// token endpoint accepts any assertion, so any RSA key works in the
+/// service-account file.
+#[derive(Clone, Default)]
+pub struct MockSheets {
+    spreadsheets: Arc<RwLock<Spreadsheets>>,
+}
+
+impl MockSheets {
+    /// Loads tabs from a JSON file shaped like `{"<spreadsheet id>": {"Sheet1": [["ORDER_ID", ...], ...]}}`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let raw = std::fs::read_to_string(path)?;
+        let data: HashMap<String, HashMap<String, Value>> = serde_json::from_str(&raw)?;
+        let spreadsheets = data
+            .into_iter()
+            .map(|(id, tabs)| {
+                let tabs = tabs
+                    .into_iter()
+                    .map(|(name, rows)| (name, value_rows(&json!({ "values": rows }))))
+                    .collect();
+                (id, tabs)
+            })
+            .collect();
+        Ok(Self { spreadsheets: Arc::new(RwLock::new(spreadsheets)) })
+    }
+
+    pub fn set_tab(&self, spreadsheet_id: &str, sheet_name: &str, rows: Grid) {
+        self.spreadsheets
+            .write()
+            .unwrap()
+            .entry(spreadsheet_id.to_string())
+            .or_default()
+            .insert(sheet_name.to_string(), rows);
+    }
+
+    /// Registers the mock routes, for use in an `App` or `actix_web::test::init_service`.
+    pub fn config(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(web::Data::new(self.clone()))
+            .service(web::resource("/token").route(web::post().to(token)))
+            .service(
+                web::resource(format!("{}/{{spreadsheet_id}}/values:batchUpdate", API_PREFIX)).route(
+                    web::post().to(batch_update)
+                )
+            )
+            .service(
+                web::resource(format!("{}/{{spreadsheet_id}}/values:batchGet", API_PREFIX)).route(
+                    web::get().to(batch_get)
+                )
+            )
+            .service(
+                web::resource(format!("{}/{{spreadsheet_id}}/values/{{range}}", API_PREFIX)).route(
+                    web::get().to(get_values)
+                )
+            );
+    }
+
+    pub async fn serve(self, bind_address: &str) -> std::io::Result<()> {
+        tracing::info!("Mock Sheets API listening on http://{}{}", bind_address, API_PREFIX);
+        HttpServer::new(move || App::new().configure(|cfg| self.config(cfg)))
+            .bind(bind_address)?
+            .run().await
+    }
+}
+
+#[cfg(test)]
+impl MockSheets {
+    /// A copy of one tab's cells, for tests to check what was written.
+    pub fn tab(&self, spreadsheet_id: &str, sheet_name: &str) -> Option<Grid> {
+        self.spreadsheets.read().unwrap().get(spreadsheet_id)?.get(sheet_name).cloned()
+    }
+
+    /// Serves the mock on a free local port for the rest of the test, and
+    /// returns settings pointing the sync and write-back at it, with Sheet1 and
+    /// Sheet2 of `spreadsheet_id` as the default target.
+    pub fn start(&self, spreadsheet_id: &str, lmdb_path: &Path) -> std::io::Result<Settings> {
+        let mock = self.clone();
+        let server = HttpServer::new(move || App::new().configure(|cfg| mock.config(cfg)))
+            .workers(1)
+            .bind("127.0.0.1:0")?;
+        let base = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        let location = |sheet_name: &str| SheetLocation {
+            spreadsheet_id: spreadsheet_id.to_string(),
+            sheet_name: sheet_name.to_string(),
+        };
+        Ok(Settings {
+            service_account: ServiceAccountKey {
+                client_email: "sync@mock.iam.gserviceaccount.com".to_string(),
+                // Throwaway key; the mock token endpoint doesn't check the signature
+                private_key: include_str!("testdata/mock_service_account.pem").into(),
+                token_uri: format!("{}/token", base),
+            },
+            sheets_api_url: format!("{}{}", base, API_PREFIX),
+            sheet1: location("Sheet1"),
+            sheet2: location("Sheet2"),
+            columns: ColumnAliases::default(),
+            import_dir: None,
+            lmdb_path: lmdb_path.to_path_buf(),
+            bind_address: "127.0.0.1:0".to_string(),
+            sync_interval: Duration::from_secs(3600),
+            sync_jitter: Duration::ZERO,
+            http_timeout: Duration::from_secs(5),
+            requests_per_minute: 600,
+            max_retries: 0,
+        })
+    }
+}
+
+/// A parsed A1 range. Bounds are 1-based rows and 0-based columns, inclusive; `None` is unbounded.
+#[derive(Debug, PartialEq, Eq)]
+struct A1Range {
+    sheet: String,
+    first_row: usize,
+    last_row: Option<usize>,
+    first_col: usize,
+    last_col: Option<usize>,
+}
+
+impl A1Range {
+    fn parse(range: &str) -> Option<Self> {
+        let (sheet, cells) = split_sheet_name(range)?;
+        let whole = Self { sheet, first_row: 1, last_row: None, first_col: 0, last_col: None };
+        let Some(cells) = cells else {
+            return Some(whole);
+        };
+
+        let (start, end) = match cells.split_once(':') {
+            Some((start, end)) => (parse_cell(start)?, parse_cell(end)?),
+            // A single cell addresses just that cell
+            None => {
+                let cell = parse_cell(cells)?;
+                (cell, cell)
+            }
+        };
+        Some(Self {
+            first_row: start.0.unwrap_or(1),
+            last_row: end.0,
+            first_col: start.1.unwrap_or(0),
+            last_col: end.1,
+            ..whole
+        })
+    }
+}
+
+/// Splits `'My Sheet'!A1:B2` into the unquoted name and the cell part.
+fn split_sheet_name(range: &str) -> Option<(String, Option<&str>)> {
+    if let Some(quoted) = range.strip_prefix('\'') {
+        let mut name = String::new();
+        let mut chars = quoted.char_indices().peekable();
+        while let Some((idx, c)) = chars.next() {
+            if c != '\'' {
+                name.push(c);
+            } else if chars.peek().map(|(_, c)| *c) == Some('\'') {
+                name.push('\'');
+                chars.next();
+            } else {
+                let rest = &quoted[idx + 1..];
+                return match rest.strip_prefix('!') {
+                    Some(cells) => Some((name, Some(cells))),
+                    None if rest.is_empty() => Some((name, None)),
+                    None => None,
+                };
+            }
+        }
+        return None;
+    }
+
+    match range.split_once('!') {
+        Some((name, cells)) => Some((name.to_string(), Some(cells))),
+        None => Some((range.to_string(), None)),
+    }
+}
+
+/// `B12` -> (Some(12), Some(1)), `C` -> (None, Some(2)), `7` -> (Some(7), None).
+fn parse_cell(cell: &str) -> Option<(Option<usize>, Option<usize>)> {
+    let split = cell.find(|c: char| c.is_ascii_digit()).unwrap_or(cell.len());
+    let (letters, digits) = cell.split_at(split);
+    if letters.is_empty() && digits.is_empty() {
+        return None;
+    }
+
+    let col = if letters.is_empty() {
+        None
+    } else {
+        let mut idx = 0usize;
+        for c in letters.chars() {
+            if !c.is_ascii_alphabetic() {
+                return None;
+            }
+            idx = idx * 26 + (c.to_ascii_uppercase() as usize - 'A' as usize + 1);
+        }
+        Some(idx - 1)
+    };
+    let row = if digits.is_empty() {
+        None
+    } else {
+        Some(digits.parse::<usize>().ok().filter(|r| *r > 0)?)
+    };
+    Some((row, col))
+}
+
+fn google_error(status: u16, reason: &str, message: String) -> HttpResponse {
+    let body = json!({ "error": { "code": status, "message": message, "status": reason } });
+    match status {
+        400 => HttpResponse::BadRequest().json(body),
+        401 => HttpResponse::Unauthorized().json(body),
+        _ => HttpResponse::NotFound().json(body),
+    }
+}
+
+fn authorized(req: &HttpRequest) -> bool {
+    req.headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        == Some(MOCK_ACCESS_TOKEN)
+}
+
+fn unauthorized() -> HttpResponse {
+    google_error(401, "UNAUTHENTICATED", "Request had invalid authentication credentials.".to_string())
+}
+
+/// Hands out the fixed mock token for any service-account assertion.
+async fn token() -> impl Responder {
+    HttpResponse::Ok().json(json!({
+        "access_token": MOCK_ACCESS_TOKEN,
+        "token_type": "Bearer",
+        "expires_in": 3600,
+    }))
+}
+
+/// The `ValueRange` for `range`, with trailing blank cells and rows trimmed like Google does.
+/// `None` when the range doesn't parse or names a missing tab.
+fn value_range(tabs: &HashMap<String, Grid>, range: &str) -> Option<Value> {
+    let bounds = A1Range::parse(range)?;
+    let grid = tabs.get(&bounds.sheet)?;
+
+    let mut values: Vec<Vec<String>> = grid
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| (bounds.first_row..=bounds.last_row.unwrap_or(usize::MAX)).contains(&(idx + 1)))
+        .map(|(_, row)| {
+            let mut cells: Vec<String> = row
+                .iter()
+                .enumerate()
+                .filter(|(col, _)| (bounds.first_col..=bounds.last_col.unwrap_or(usize::MAX)).contains(col))
+                .map(|(_, cell)| cell.clone())
+                .collect();
+            while cells.last().is_some_and(|c| c.is_empty()) {
+                cells.pop();
+            }
+            cells
+        })
+        .collect();
+    while values.last().is_some_and(|row| row.is_empty()) {
+        values.pop();
+    }
+
+    let mut body = json!({ "range": range, "majorDimension": "ROWS" });
+    if !values.is_empty() {
+        body["values"] = json!(values);
+    }
+    Some(body)
+}
+
+fn not_found() -> HttpResponse {
+    google_error(404, "NOT_FOUND", "Requested entity was not found.".to_string())
+}
+
+fn bad_range(range: &str) -> HttpResponse {
+    google_error(400, "INVALID_ARGUMENT", format!("Unable to parse range: {}", range))
+}
+
+/// `GET /v4/spreadsheets/{id}/values/{range}`
+async fn get_values(
+    req: HttpRequest,
+    mock: web::Data<MockSheets>,
+    path: web::Path<(String, String)>
+) -> impl Responder {
+    if !authorized(&req) {
+        return unauthorized();
+    }
+    let (spreadsheet_id, range) = path.into_inner();
+    let spreadsheets = mock.spreadsheets.read().unwrap();
+    let Some(tabs) = spreadsheets.get(&spreadsheet_id) else {
+        return not_found();
+    };
+
+    match value_range(tabs, &range) {
+        Some(body) => HttpResponse::Ok().json(body),
+        None => bad_range(&range),
+    }
+}
+
+/// `GET /v4/spreadsheets/{id}/values:batchGet?ranges=...&ranges=...`. Only `majorDimension=ROWS` is supported.
+async fn batch_get(
+    req: HttpRequest,
+    mock: web::Data<MockSheets>,
+    path: web::Path<String>,
+    query: web::Query<Vec<(String, String)>>
+) -> impl Responder {
+    if !authorized(&req) {
+        return unauthorized();
+    }
+    let spreadsheet_id = path.into_inner();
+    let spreadsheets = mock.spreadsheets.read().unwrap();
+    let Some(tabs) = spreadsheets.get(&spreadsheet_id) else {
+        return not_found();
+    };
+
+    let mut value_ranges = Vec::new();
+    for (key, value) in query.iter() {
+        match key.as_str() {
+            "ranges" =>
+                match value_range(tabs, value) {
+                    Some(body) => value_ranges.push(body),
+                    None => {
+                        return bad_range(value);
+                    }
+                }
+            "majorDimension" if value != "ROWS" => {
+                return google_error(400, "INVALID_ARGUMENT", format!("Unsupported majorDimension: {}", value));
+            }
+            _ => {}
+        }
+    }
+
+    HttpResponse::Ok().json(json!({ "spreadsheetId": spreadsheet_id, "valueRanges": value_ranges }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ValueRange {
+    range: String,
+    values: Vec<Vec<Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchUpdateRequest {
+    data: Vec<ValueRange>,
+}
+
+/// `POST /v4/spreadsheets/{id}/values:batchUpdate`, writing each block of rows at its range's top-left cell.
+async fn batch_update(
+    req: HttpRequest,
+    mock: web::Data<MockSheets>,
+    path: web::Path<String>,
+    body: web::Json<BatchUpdateRequest>
+) -> impl Responder {
+    if !authorized(&req) {
+        return unauthorized();
+    }
+    let spreadsheet_id = path.into_inner();
+    let mut spreadsheets = mock.spreadsheets.write().unwrap();
+    let Some(tabs) = spreadsheets.get_mut(&spreadsheet_id) else {
+        return not_found();
+    };
+
+    // Validate everything first so a bad range leaves the sheet untouched, like the real API
+    let mut writes = Vec::new();
+    for update in &body.data {
+        match A1Range::parse(&update.range).filter(|r| tabs.contains_key(&r.sheet)) {
+            Some(bounds) => writes.push((bounds, &update.values)),
+            None => {
+                return bad_range(&update.range);
+            }
+        }
+    }
+
+    let mut updated_cells = 0;
+    for (bounds, values) in writes {
+        let grid = tabs.get_mut(&bounds.sheet).expect("checked above");
+        for (offset, row) in values.iter().enumerate() {
+            let row_idx = bounds.first_row - 1 + offset;
+            if grid.len() <= row_idx {
+                grid.resize(row_idx + 1, Vec::new());
+            }
+            let target = &mut grid[row_idx];
+            for (col_offset, value) in row.iter().enumerate() {
+                let col = bounds.first_col + col_offset;
+                if target.len() <= col {
+                    target.resize(col + 1, String::new());
+                }
+                target[col] = match value {
+                    Value::String(s) => s.clone(),
+                    Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                updated_cells += 1;
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(json!({
+        "spreadsheetId": spreadsheet_id,
+        "totalUpdatedRanges": body.data.len(),
+        "totalUpdatedCells": updated_cells,
+    }))
+}
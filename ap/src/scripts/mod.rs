@@ -1,2 +1,12 @@
 pub mod google_sheet_order;
-pub mod utils;
\ No newline at end of file
+pub mod mock_sheets;
+pub mod utils;
+pub mod token;
+pub mod scheduler;
+pub mod sheet_writeback;
+pub mod sheets_client;
+pub mod source;
+pub mod sync_plan;
+pub mod conflicts;
+pub mod targets;
+pub mod cli;
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+
+use chrono::Utc;
+use rand::Rng;
+use serde::Serialize;
+use tokio::sync::{ Mutex, OwnedMutexGuard, RwLock };
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::config::settings::Settings;
+use crate::lmdb::utils::DB;
+use crate::schema::sync::{ SyncReport, TargetSummary };
+use crate::scripts::google_sheet_order::sync_sheets;
+use crate::scripts::sheet_writeback::SheetWriter;
+use crate::scripts::sheets_client::SheetsClient;
+
+/// Outcome of the most recent sheet sync runs, kept in memory.
+///
+/// `targets` holds each sync target's own outcome, so a run of one target
+/// leaves the others' as they were.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct SyncStatus {
+    pub running: bool,
+    pub runs: u64,
+    pub last_started_at: Option<String>,
+    pub last_finished_at: Option<String>,
+    pub last_duration_ms: Option<u64>,
+    /// Why the last run failed before it got to any target, e.g. the target
+    /// list couldn't be read. Cleared by the next run that gets past it.
+    pub last_run_error: Option<String>,
+    pub targets: BTreeMap<String, TargetStatus>,
+}
+
+/// The last sync outcome of one target.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct TargetStatus {
+    pub last_success_at: Option<String>,
+    /// Where its last failed run stopped; cleared by the next success.
+    pub last_error: Option<String>,
+    /// Per-source row counts of its last successful run.
+    pub last_summary: Option<TargetSummary>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RunOutcome {
+    Completed,
+    Failed(String),
+    /// Another run held the lock, so this one did nothing.
+    Skipped,
+}
+
+/// Runs the Google Sheets import in the background so the API never waits on it.
+#[derive(Clone)]
+pub struct SyncJob {
+    settings: Arc<Settings>,
+    sheets: SheetsClient,
+    db: DB,
+    writer: SheetWriter,
+    status: Arc<RwLock<SyncStatus>>,
+    run_lock: Arc<Mutex<()>>,
+}
+
+impl SyncJob {
+    pub fn new(settings: Arc<Settings>, sheets: SheetsClient, db: DB, writer: SheetWriter) -> Self {
+        Self {
+            settings,
+            sheets,
+            db,
+            writer,
+            status: Arc::new(RwLock::new(SyncStatus::default())),
+            run_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub async fn status(&self) -> SyncStatus {
+        self.status.read().await.clone()
+    }
+
+    /// Syncs every enabled target unless another run is already in progress.
+    pub async fn run_once(&self) -> RunOutcome {
+        let Ok(guard) = self.run_lock.clone().try_lock_owned() else {
+            tracing::info!("Sheet sync already running, skipping");
+            return RunOutcome::Skipped;
+        };
+        self.run_locked(guard, None).await
+    }
+
+    /// Starts a sync of every enabled target, or of `target` alone, in the
+    /// background. Returns false, and starts nothing, when a run is already in progress.
+    pub fn trigger(&self, target: Option<String>) -> bool {
+        let Ok(guard) = self.run_lock.clone().try_lock_owned() else {
+            return false;
+        };
+        let job = self.clone();
+        tokio::spawn(async move {
+            job.run_locked(guard, target.as_deref()).await;
+        });
+        true
+    }
+
+    /// Does the actual run; the guard proves the run lock is held until it finishes.
+    async fn run_locked(&self, _guard: OwnedMutexGuard<()>, target: Option<&str>) -> RunOutcome {
+        let started = Instant::now();
+        {
+            let mut status = self.status.write().await;
+            status.running = true;
+            status.last_started_at = Some(Utc::now().to_rfc3339());
+        }
+
+        // Flush queued API edits first so the import doesn't read rows they are about to change.
+        if let Err(e) = self.writer.retry_pending().await {
+            tracing::warn!("Retrying queued sheet write-backs failed: {}", e);
+        }
+
+        let result = sync_sheets(&self.settings, &self.sheets, &self.db, false, target).await;
+
+        let mut status = self.status.write().await;
+        let finished_at = Utc::now().to_rfc3339();
+        status.running = false;
+        status.runs += 1;
+        status.last_duration_ms = Some(started.elapsed().as_millis() as u64);
+        status.last_finished_at = Some(finished_at.clone());
+
+        match result {
+            Ok(report) => {
+                status.last_run_error = None;
+                for summary in report.summary.targets {
+                    let target = status.targets.entry(summary.target.clone()).or_default();
+                    target.last_success_at = Some(finished_at.clone());
+                    target.last_error = None;
+                    target.last_summary = Some(summary);
+                }
+                RunOutcome::Completed
+            }
+            Err(e) => {
+                tracing::warn!("Sheet sync of {:?} failed: {}", e.targets, e);
+                status.last_run_error = e.targets.is_empty().then(|| e.message.clone());
+                for id in &e.targets {
+                    status.targets.entry(id.clone()).or_default().last_error = Some(e.message.clone());
+                }
+                RunOutcome::Failed(e.message)
+            }
+        }
+    }
+
+    /// Computes what a sync would change without writing anything. Doesn't
+    /// take the run lock, since it can't conflict with a real run.
+    pub async fn dry_run(&self, target: Option<&str>) -> Result<SyncReport, String> {
+        sync_sheets(&self.settings, &self.sheets, &self.db, true, target).await.map_err(|e| e.to_string())
+    }
+
+    /// Starts the periodic loop: one run right away, then one every
+    /// `sync_interval` plus up to `sync_jitter` of random delay.
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                self.run_once().await;
+                tokio::time::sleep(self.next_delay()).await;
+            }
+        })
+    }
+
+    fn next_delay(&self) -> Duration {
+        let jitter = self.settings.sync_jitter.as_millis() as u64;
+        let extra = if jitter == 0 { 0 } else { rand::rng().random_range(0..=jitter) };
+        self.settings.sync_interval + Duration::from_millis(extra)
+    }
+}
@@ -0,0 +1,252 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use serde_json::{ json, Value };
+
+use crate::config::columns;
+use crate::config::settings::{ SheetLocation, Settings };
+use crate::lmdb::order::DBOrder;
+use crate::lmdb::utils::DB;
+use crate::lmdb::writeback::DBWriteback;
+use crate::schema::order::Order;
+use crate::scripts::google_sheet_order::a1_range;
+use crate::scripts::sheets_client::SheetsClient;
+use crate::scripts::targets::{ find_target, DEFAULT_TARGET };
+use crate::scripts::utils::{ changed_cells, column_letter, sheet1_fields, sheet2_fields, HeaderMap };
+
+type WriteResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// A row to patch: its tab, 1-based row, and the cells to change as 0-based column and text.
+type RowUpdate<'a> = (&'a SheetLocation, usize, Vec<(usize, String)>);
+
+/// Result of pushing an order to the sheets.
+#[derive(Debug, PartialEq, Eq)]
+pub enum WritebackOutcome {
+    Written,
+    /// The write failed and the order was queued for the next sync run.
+    Queued(String),
+}
+
+/// Pushes API-side order changes back into Sheet1/Sheet2.
+#[derive(Clone)]
+pub struct SheetWriter {
+    settings: Arc<Settings>,
+    sheets: SheetsClient,
+    db: DB,
+}
+
+impl SheetWriter {
+    pub fn new(settings: Arc<Settings>, sheets: SheetsClient, db: DB) -> Self {
+        Self { settings, sheets, db }
+    }
+
+    /// Writes the order to the sheets, queueing it for retry on failure.
+    pub async fn write_or_queue(&self, order: &Order) -> WritebackOutcome {
+        match self.write_order(order).await {
+            Ok(()) => {
+                // A successful write supersedes anything still queued for this order.
+                if let Err(e) = self.db.remove_writeback(&order.id) {
+                    tracing::warn!("Failed to clear write-back queue for {}: {}", order.id, e);
+                }
+                WritebackOutcome::Written
+            }
+            Err(e) => {
+                let message = e.to_string();
+                tracing::warn!("Sheet write-back for {} failed, queueing: {}", order.id, message);
+                if let Err(e) = self.db.enqueue_writeback(&order.id, &message) {
+                    tracing::error!("Failed to queue write-back for {}: {}", order.id, e);
+                }
+                WritebackOutcome::Queued(message)
+            }
+        }
+    }
+
+    /// Writes the order's stored value to the sheets in the background, so a
+    /// slow or rate-limited Sheets API doesn't hold up the request that changed
+    /// it. Failures are queued for retry like any other write-back.
+    pub fn spawn_write(&self, id: String) {
+        let writer = self.clone();
+        tokio::spawn(async move {
+            // Read when the task runs, so the newest of several quick edits is what gets written
+            match writer.db.get_single(id.clone()) {
+                Ok(Some(order)) => {
+                    writer.write_or_queue(&order).await;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to read order {} for sheet write-back, queueing: {}", id, e);
+                    if let Err(e) = writer.db.enqueue_writeback(&id, &e.to_string()) {
+                        tracing::error!("Failed to queue write-back for {}: {}", id, e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Retries every queued write-back with the order's current stored value.
+    pub async fn retry_pending(&self) -> WriteResult<()> {
+        let pending = self.db.pending_writebacks().map_err(|e| e.to_string())?;
+
+        for entry in pending {
+            let order = match self.db.get_single(entry.id.clone()) {
+                Ok(Some(order)) => order,
+                Ok(None) => {
+                    // Deleted since it was queued, nothing left to write.
+                    self.db.remove_writeback(&entry.id).map_err(|e| e.to_string())?;
+                    continue;
+                }
+                Err(e) => {
+                    return Err(e.to_string().into());
+                }
+            };
+
+            if let WritebackOutcome::Queued(e) = self.write_or_queue(&order).await {
+                tracing::info!(
+                    "Write-back for {} still failing after {} attempts: {}",
+                    entry.id,
+                    entry.attempts + 1,
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Patches the order's Sheet1 row and, when one matches, its Sheet2 row,
+    /// in the tabs of the sync target the order came from.
+    pub async fn write_order(&self, order: &Order) -> WriteResult<()> {
+        let target_id = order.source.as_deref().unwrap_or(DEFAULT_TARGET);
+        let target = find_target(&self.settings, &self.db, target_id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Sync target {} of order {} no longer exists", target_id, order.id))?;
+        let sheet1 = &target.sheet1;
+        let sheet2 = &target.sheet2;
+
+        // One batchGet for both headers, all of Sheet2, and the Sheet1 row the order was last seen on
+        let stored_row = order.row_number.map(|n| n as usize + 1);
+        let mut ranges = vec![(sheet1, "1:1".to_string()), (sheet2, String::new())];
+        if let Some(row) = stored_row {
+            ranges.push((sheet1, format!("{}:{}", row, row)));
+        }
+        let mut grids = self.sheets.read_ranges(&ranges).await?.into_iter();
+        let sheet1_header = grids.next().and_then(|g| g.into_iter().next()).unwrap_or_default();
+        let sheet2_rows = grids.next().unwrap_or_default();
+        let stored_values = grids.next().and_then(|g| g.into_iter().next()).unwrap_or_default();
+
+        let sheet1_headers = HeaderMap::resolve(&sheet1.sheet_name, &sheet1_header, &target.columns.sheet1)?;
+        let (sheet1_row, sheet1_values) = match stored_row {
+            // The stored row_number is only trusted while it still holds this order
+            Some(row) if sheet1_headers.cell(&stored_values, columns::ORDER_ID) == Some(order.order_id.as_str()) => {
+                (row, stored_values)
+            }
+            _ => {
+                let row = self.find_sheet1_row(sheet1, &sheet1_headers, order).await?.ok_or_else(||
+                    format!("Order {} not found in {}", order.order_id, sheet1.sheet_name)
+                )?;
+                (row, self.fetch_row(sheet1, row).await?)
+            }
+        };
+
+        let sheet2_header = sheet2_rows.first().cloned().unwrap_or_default();
+        let sheet2_headers = HeaderMap::resolve(&sheet2.sheet_name, &sheet2_header, &target.columns.sheet2)?;
+        let sheet2_match = find_sheet2_row(&sheet2_rows, &sheet2_headers, order);
+
+        // Only the cells that differ are sent, so columns the API doesn't own are never touched
+        let sheet1_cells = changed_cells(&sheet1_values, &sheet1_headers, sheet1_fields(order));
+        let mut updates = vec![(sheet1, sheet1_row, sheet1_cells)];
+        match sheet2_match {
+            Some((row, values)) => {
+                updates.push((sheet2, row, changed_cells(&values, &sheet2_headers, sheet2_fields(order))));
+            }
+            None => tracing::debug!("No {} row for order {}, skipping", sheet2.sheet_name, order.order_id),
+        }
+
+        self.batch_update(&updates).await
+    }
+
+    /// Scans the ORDER_ID column for the order. Returns a 1-based sheet row.
+    async fn find_sheet1_row(
+        &self,
+        sheet: &SheetLocation,
+        headers: &HeaderMap,
+        order: &Order
+    ) -> WriteResult<Option<usize>> {
+        let order_id_col = headers
+            .index(columns::ORDER_ID)
+            .ok_or_else(|| format!("{} has no {} column", sheet.sheet_name, columns::ORDER_ID))?;
+
+        let column = column_letter(order_id_col);
+        let rows = self.fetch_values(sheet, &format!("{}:{}", column, column)).await?;
+        Ok(
+            rows
+                .iter()
+                .position(|row| row.first().map(String::as_str) == Some(order.order_id.as_str()))
+                .map(|idx| idx + 1)
+        )
+    }
+
+    async fn fetch_row(&self, sheet: &SheetLocation, row: usize) -> WriteResult<Vec<String>> {
+        let rows = self.fetch_values(sheet, &format!("{}:{}", row, row)).await?;
+        Ok(rows.into_iter().next().unwrap_or_default())
+    }
+
+    async fn fetch_values(&self, sheet: &SheetLocation, range: &str) -> WriteResult<Vec<Vec<String>>> {
+        let range = a1_range(&sheet.sheet_name, range);
+        Ok(self.sheets.get_values(&sheet.spreadsheet_id, &range).await?)
+    }
+
+    /// Sends one `values:batchUpdate` per spreadsheet with cells to change,
+    /// with a single-cell range per changed cell.
+    async fn batch_update(
+        &self,
+        updates: &[RowUpdate<'_>]
+    ) -> WriteResult<()> {
+        let mut spreadsheets: Vec<&str> = updates
+            .iter()
+            .filter(|(_, _, cells)| !cells.is_empty())
+            .map(|(sheet, _, _)| sheet.spreadsheet_id.as_str())
+            .collect();
+        spreadsheets.dedup();
+
+        for spreadsheet_id in spreadsheets {
+            let data: Vec<Value> = updates
+                .iter()
+                .filter(|(sheet, _, _)| sheet.spreadsheet_id == spreadsheet_id)
+                .flat_map(|(sheet, row, cells)| {
+                    cells.iter().map(move |(col, value)| {
+                        json!({
+                            "range": a1_range(&sheet.sheet_name, &format!("{}{}", column_letter(*col), row)),
+                            "majorDimension": "ROWS",
+                            "values": [[value]],
+                        })
+                    })
+                })
+                .collect();
+
+            self.sheets.batch_update(spreadsheet_id, data).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Sheet2 rows reference an order by RETURN_ORDER/SHOPIFY_ID, or by the
+/// Sheet1 ROW_NUMBER. Returns the 1-based row and its current cells.
+fn find_sheet2_row(rows: &[Vec<String>], headers: &HeaderMap, order: &Order) -> Option<(usize, Vec<String>)> {
+    let order_id = Some(order.order_id.as_str());
+    let by_id = rows
+        .iter()
+        .skip(1)
+        .position(|row| {
+            headers.cell(row, columns::RETURN_ORDER) == order_id ||
+                headers.cell(row, columns::SHOPIFY_ID) == order_id
+        });
+    let by_row_number = || {
+        let wanted = order.row_number?.to_string();
+        rows.iter()
+            .skip(1)
+            .position(|row| headers.cell(row, columns::ROW_NUMBER) == Some(wanted.as_str()))
+    };
+
+    // +1 for the skipped header, +1 for 1-based rows
+    by_id.or_else(by_row_number).map(|idx| (idx + 2, rows[idx + 1].clone()))
+}
@@ -0,0 +1,349 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+
+use chrono::{ DateTime, Utc };
+use rand::Rng;
+use reqwest::header::{ HeaderMap, RETRY_AFTER };
+use reqwest::{ Client, RequestBuilder, Response, StatusCode };
+use serde_json::{ json, Value };
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+use crate::config::settings::{ Settings, SheetLocation };
+use crate::scripts::google_sheet_order::a1_range;
+use crate::scripts::source::{ value_rows, Grid };
+use crate::scripts::token::{ TokenError, TokenProvider };
+
+/// First retry waits about this long, doubling on every further attempt.
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(32);
+/// Longest Retry-After we are willing to sleep for inside one call.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const BUDGET_WINDOW: Duration = Duration::from_secs(60);
+
+/// Why a Sheets API call failed. Only quota, 5xx and network errors are retried.
+#[derive(Debug, Error)]
+pub enum SheetsError {
+    #[error(transparent)]
+    Token(#[from] TokenError),
+
+    #[error("Sheets API rejected the credentials ({status}): {message}")]
+    Auth {
+        status: StatusCode,
+        message: String,
+    },
+
+    #[error("Sheets API quota exceeded after {attempts} attempts: {message}")]
+    Quota {
+        attempts: u32,
+        message: String,
+    },
+
+    #[error("Sheets API refused range {range}: {message}")]
+    BadRange {
+        range: String,
+        message: String,
+    },
+
+    #[error("spreadsheet or range {range} not found: {message}")]
+    NotFound {
+        range: String,
+        message: String,
+    },
+
+    #[error("Sheets API returned {status} after {attempts} attempts: {message}")]
+    Server {
+        status: StatusCode,
+        attempts: u32,
+        message: String,
+    },
+
+    #[error("Sheets API returned {status}: {message}")]
+    Api {
+        status: StatusCode,
+        message: String,
+    },
+
+    #[error("request to Sheets API failed after {attempts} attempts: {source}")]
+    Http {
+        attempts: u32,
+        source: reqwest::Error,
+    },
+
+    #[error("unexpected Sheets API response: {0}")]
+    Decode(reqwest::Error),
+}
+
+/// Sliding one-minute window of request start times.
+struct RequestBudget {
+    per_minute: usize,
+    sent: Mutex<VecDeque<Instant>>,
+}
+
+impl RequestBudget {
+    /// Waits until a request can be sent without exceeding the budget, then claims the slot.
+    async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut sent = self.sent.lock().await;
+                let now = Instant::now();
+                while sent.front().is_some_and(|t| now.duration_since(*t) >= BUDGET_WINDOW) {
+                    sent.pop_front();
+                }
+                if sent.len() < self.per_minute {
+                    sent.push_back(now);
+                    return;
+                }
+                BUDGET_WINDOW - now.duration_since(sent[0])
+            };
+            tracing::debug!("Sheets request budget used up, waiting {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+struct Inner {
+    base_url: String,
+    http: Client,
+    tokens: TokenProvider,
+    budget: RequestBudget,
+    max_retries: u32,
+}
+
+/// Shared Google Sheets API client: one connection pool, a request-per-minute
+/// budget, and retries with exponential backoff on quota and server errors.
+#[derive(Clone)]
+pub struct SheetsClient {
+    inner: Arc<Inner>,
+}
+
+impl SheetsClient {
+    pub fn new(settings: &Settings, http: Client, tokens: TokenProvider) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                base_url: settings.sheets_api_url.clone(),
+                http,
+                tokens,
+                budget: RequestBudget {
+                    per_minute: settings.requests_per_minute as usize,
+                    sent: Mutex::new(VecDeque::new()),
+                },
+                max_retries: settings.max_retries,
+            }),
+        }
+    }
+
+    /// The HTTP client every Google call goes through, token requests included.
+    pub fn http_client(timeout: Duration) -> Client {
+        Client::builder()
+            .timeout(timeout)
+            .connect_timeout(CONNECT_TIMEOUT.min(timeout))
+            .build()
+            .expect("Failed to build HTTP client")
+    }
+
+    /// Reads `range` (an A1 range, see [`a1_range`](crate::scripts::google_sheet_order::a1_range)).
+    pub async fn get_values(&self, spreadsheet_id: &str, range: &str) -> Result<Grid, SheetsError> {
+        let url = format!("{}/{}/values/{}", self.inner.base_url, spreadsheet_id, range);
+        let response = self.send(range, |http| http.get(&url)).await?;
+        let data = response.json::<Value>().await.map_err(SheetsError::Decode)?;
+        Ok(value_rows(&data))
+    }
+
+    /// Reads several A1 ranges of one spreadsheet in a single `values:batchGet`.
+    /// Grids come back in the order of `ranges`, each starting at its range's first row.
+    pub async fn batch_get(&self, spreadsheet_id: &str, ranges: &[String]) -> Result<Vec<Grid>, SheetsError> {
+        let url = format!("{}/{}/values:batchGet", self.inner.base_url, spreadsheet_id);
+        let mut query: Vec<(&str, &str)> = ranges
+            .iter()
+            .map(|r| ("ranges", r.as_str()))
+            .collect();
+        query.push(("majorDimension", "ROWS"));
+
+        let response = self.send(&ranges.join(","), |http| http.get(&url).query(&query)).await?;
+        let data = response.json::<Value>().await.map_err(SheetsError::Decode)?;
+        let value_ranges = data["valueRanges"].as_array().cloned().unwrap_or_default();
+        if value_ranges.len() != ranges.len() {
+            return Err(SheetsError::Api {
+                status: StatusCode::OK,
+                message: format!("asked for {} ranges, got {}", ranges.len(), value_ranges.len()),
+            });
+        }
+        Ok(value_ranges.iter().map(value_rows).collect())
+    }
+
+    /// Reads ranges spread over any number of tabs with one `values:batchGet` per
+    /// spreadsheet. `range` is relative to the tab (empty for the whole tab).
+    pub async fn read_ranges(&self, ranges: &[(&SheetLocation, String)]) -> Result<Vec<Grid>, SheetsError> {
+        let mut grids = vec![Grid::new(); ranges.len()];
+        let mut spreadsheets: Vec<&str> = ranges
+            .iter()
+            .map(|(sheet, _)| sheet.spreadsheet_id.as_str())
+            .collect();
+        spreadsheets.sort();
+        spreadsheets.dedup();
+
+        for spreadsheet_id in spreadsheets {
+            let (positions, a1): (Vec<usize>, Vec<String>) = ranges
+                .iter()
+                .enumerate()
+                .filter(|(_, (sheet, _))| sheet.spreadsheet_id == spreadsheet_id)
+                .map(|(idx, (sheet, range))| (idx, a1_range(&sheet.sheet_name, range)))
+                .unzip();
+            for (idx, grid) in positions.into_iter().zip(self.batch_get(spreadsheet_id, &a1).await?) {
+                grids[idx] = grid;
+            }
+        }
+        Ok(grids)
+    }
+
+    /// Writes raw values to several ranges of one spreadsheet in a single call.
+    /// `data` entries are `ValueRange` objects (`range`, `majorDimension`, `values`).
+    pub async fn batch_update(&self, spreadsheet_id: &str, data: Vec<Value>) -> Result<(), SheetsError> {
+        let ranges: Vec<&str> = data
+            .iter()
+            .filter_map(|d| d["range"].as_str())
+            .collect();
+        let url = format!("{}/{}/values:batchUpdate", self.inner.base_url, spreadsheet_id);
+        let body = json!({ "valueInputOption": "RAW", "data": data });
+
+        self.send(&ranges.join(","), |http| http.post(&url).json(&body)).await?;
+        Ok(())
+    }
+
+    /// Sends the request built by `build`, retrying 429s, 5xx and network errors.
+    /// A 401 drops the cached token and is retried once with a fresh one.
+    async fn send(&self, range: &str, build: impl Fn(&Client) -> RequestBuilder) -> Result<Response, SheetsError> {
+        let inner = &self.inner;
+        let mut attempt = 0;
+        let mut reauthenticated = false;
+
+        loop {
+            inner.budget.acquire().await;
+            let token = inner.tokens.access_token().await?;
+            let attempts = attempt + 1;
+
+            let (retry_after, error) = match build(&inner.http).bearer_auth(token).send().await {
+                Ok(response) if response.status().is_success() => {
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = retry_after(response.headers());
+                    let message = error_message(response.text().await.unwrap_or_default());
+                    match status {
+                        StatusCode::UNAUTHORIZED if !reauthenticated => {
+                            tracing::info!("Sheets API returned 401, refreshing the access token");
+                            inner.tokens.invalidate().await;
+                            reauthenticated = true;
+                            continue;
+                        }
+                        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                            return Err(SheetsError::Auth { status, message });
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            return Err(SheetsError::BadRange { range: range.to_string(), message });
+                        }
+                        StatusCode::NOT_FOUND => {
+                            return Err(SheetsError::NotFound { range: range.to_string(), message });
+                        }
+                        StatusCode::TOO_MANY_REQUESTS => (retry_after, SheetsError::Quota { attempts, message }),
+                        status if status.is_server_error() => {
+                            (retry_after, SheetsError::Server { status, attempts, message })
+                        }
+                        status => {
+                            return Err(SheetsError::Api { status, message });
+                        }
+                    }
+                }
+                Err(source) if source.is_timeout() || source.is_connect() || source.is_request() => {
+                    (None, SheetsError::Http { attempts, source })
+                }
+                Err(source) => {
+                    return Err(SheetsError::Http { attempts, source });
+                }
+            };
+
+            if attempt >= inner.max_retries {
+                return Err(error);
+            }
+            let delay = retry_after.unwrap_or_else(|| backoff(attempt));
+            tracing::warn!("Sheets request for {} failed ({}), retrying in {:?}", range, error, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Exponential backoff with jitter: half the nominal delay fixed, half random.
+fn backoff(attempt: u32) -> Duration {
+    let nominal = BASE_BACKOFF.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_BACKOFF);
+    let half = nominal.as_millis() as u64 / 2;
+    Duration::from_millis(half + rand::rng().random_range(0..=half))
+}
+
+/// Retry-After as either delay-seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let at = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+            (at - Utc::now()).to_std().unwrap_or_default()
+        }
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+/// Google wraps errors as `{"error": {"message": ...}}`; fall back to the raw body.
+fn error_message(body: String) -> String {
+    serde_json
+        ::from_str::<Value>(&body)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_with_jitter() {
+        for attempt in 0..12 {
+            let nominal = BASE_BACKOFF.saturating_mul(2u32.pow(attempt)).min(MAX_BACKOFF);
+            for _ in 0..20 {
+                let delay = backoff(attempt);
+                assert!(delay >= nominal / 2 && delay <= nominal, "attempt {}: {:?}", attempt, delay);
+            }
+        }
+        // Far past the cap the exponent must not overflow
+        assert!(backoff(u32::MAX) <= MAX_BACKOFF);
+    }
+
+    #[test]
+    fn retry_after_takes_seconds_or_a_date() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("7")), Some(Duration::from_secs(7)));
+        assert_eq!(retry_after(&headers(" 0 ")), Some(Duration::ZERO));
+        assert_eq!(retry_after(&headers("86400")), Some(MAX_RETRY_AFTER));
+        assert_eq!(retry_after(&headers("soon")), None);
+
+        let at = (Utc::now() + chrono::Duration::seconds(60)).format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let delay = retry_after(&headers(&at)).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60), "{:?}", delay);
+
+        // A date already past means retry now
+        assert_eq!(retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")), Some(Duration::ZERO));
+    }
+}
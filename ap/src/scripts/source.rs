@@ -0,0 +1,217 @@
+use std::future::Future;
+use std::path::{ Path, PathBuf };
+
+use calamine::{ open_workbook_auto, Reader };
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::config::settings::SheetLocation;
+use crate::scripts::sheets_client::{ SheetsClient, SheetsError };
+
+/// A tab's cells as rows of strings, header row first. Trailing empty cells may be missing.
+pub type Grid = Vec<Vec<String>>;
+
+#[derive(Debug, Error)]
+pub enum SourceError {
+    #[error(transparent)]
+    Sheets(#[from] SheetsError),
+
+    #[error("failed to read {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("failed to parse CSV {path:?}: {source}")]
+    Csv {
+        path: PathBuf,
+        source: csv::Error,
+    },
+
+    #[error("failed to read workbook {path:?}: {reason}")]
+    Workbook {
+        path: PathBuf,
+        reason: String,
+    },
+
+    #[error("unsupported file type {0:?}, expected .csv or .xlsx")]
+    UnsupportedFormat(PathBuf),
+}
+
+/// Anything the order importer can read a grid of rows from.
+pub trait SheetSource {
+    /// Short label for logs and reports, e.g. the tab or file name.
+    fn name(&self) -> String;
+
+    /// Whether grid row `i` is row `i + 1` of a live sheet, so write-back can address it.
+    fn row_addressable(&self) -> bool {
+        false
+    }
+
+    fn read_rows(&self) -> impl Future<Output = Result<Grid, SourceError>> + Send;
+}
+
+/// A tab in a Google spreadsheet, read up front so every tab of a sync comes
+/// from the same `values:batchGet`.
+#[derive(Debug, Clone)]
+pub struct GoogleSheetSource {
+    location: SheetLocation,
+    rows: Grid,
+}
+
+impl GoogleSheetSource {
+    /// Reads every tab in `locations`, one request per spreadsheet, returned in the same order.
+    pub async fn fetch_all(sheets: &SheetsClient, locations: &[SheetLocation]) -> Result<Vec<Self>, SourceError> {
+        let ranges: Vec<(&SheetLocation, String)> = locations
+            .iter()
+            .map(|location| (location, String::new()))
+            .collect();
+        let grids = sheets.read_ranges(&ranges).await?;
+
+        Ok(
+            locations
+                .iter()
+                .cloned()
+                .zip(grids)
+                .map(|(location, rows)| Self { location, rows })
+                .collect()
+        )
+    }
+}
+
+impl SheetSource for GoogleSheetSource {
+    fn name(&self) -> String {
+        self.location.sheet_name.clone()
+    }
+
+    fn row_addressable(&self) -> bool {
+        true
+    }
+
+    async fn read_rows(&self) -> Result<Grid, SourceError> {
+        Ok(self.rows.clone())
+    }
+}
+
+/// A marketplace export on disk. `.csv` is read as-is; for `.xlsx`/`.xls`/`.ods`
+/// the first worksheet is used.
+#[derive(Debug, Clone)]
+pub struct FileSheetSource {
+    path: PathBuf,
+}
+
+impl FileSheetSource {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self { path: path.as_ref().to_path_buf() }
+    }
+
+    fn is_supported(path: &Path) -> bool {
+        matches!(extension(path).as_deref(), Some("csv" | "xlsx" | "xls" | "xlsm" | "ods"))
+    }
+
+    fn read_blocking(&self) -> Result<Grid, SourceError> {
+        match extension(&self.path).as_deref() {
+            Some("csv") => read_csv(&self.path),
+            Some("xlsx" | "xls" | "xlsm" | "ods") => read_workbook(&self.path),
+            _ => Err(SourceError::UnsupportedFormat(self.path.clone())),
+        }
+    }
+}
+
+impl SheetSource for FileSheetSource {
+    fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    async fn read_rows(&self) -> Result<Grid, SourceError> {
+        let source = self.clone();
+        tokio::task
+            ::spawn_blocking(move || source.read_blocking()).await
+            .map_err(|e| SourceError::Io { path: self.path.clone(), source: std::io::Error::other(e) })?
+    }
+}
+
+/// Every supported export in `dir`, sorted by file name so imports are repeatable.
+pub fn directory_sources<P: AsRef<Path>>(dir: P) -> Result<Vec<FileSheetSource>, SourceError> {
+    let dir = dir.as_ref();
+    let io_err = |source| SourceError::Io { path: dir.to_path_buf(), source };
+
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(io_err)? {
+        let path = entry.map_err(io_err)?.path();
+        if path.is_file() && FileSheetSource::is_supported(&path) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths.into_iter().map(FileSheetSource::new).collect())
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase())
+}
+
+fn read_csv(path: &Path) -> Result<Grid, SourceError> {
+    let mut reader = csv::ReaderBuilder
+        ::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|source| SourceError::Csv { path: path.to_path_buf(), source })?;
+
+    reader
+        .records()
+        .map(|record| {
+            record
+                .map(|r| r.iter().map(str::to_string).collect())
+                .map_err(|source| SourceError::Csv { path: path.to_path_buf(), source })
+        })
+        .collect()
+}
+
+fn read_workbook(path: &Path) -> Result<Grid, SourceError> {
+    let workbook_err = |reason: String| SourceError::Workbook { path: path.to_path_buf(), reason };
+
+    let mut workbook = open_workbook_auto(path).map_err(|e| workbook_err(e.to_string()))?;
+    let name = workbook
+        .sheet_names()
+        .first()
+        .cloned()
+        .ok_or_else(|| workbook_err("workbook has no worksheets".to_string()))?;
+    let range = workbook.worksheet_range(&name).map_err(|e| workbook_err(e.to_string()))?;
+
+    Ok(
+        range
+            .rows()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect()
+    )
+}
+
+/// Converts a Sheets `values` response into rows of cell strings.
+pub fn value_rows(data: &Value) -> Grid {
+    data["values"]
+        .as_array()
+        .map(|rows| {
+            rows.iter()
+                .map(|row| {
+                    row.as_array()
+                        .map(|cells| {
+                            cells
+                                .iter()
+                                .map(|cell| match cell {
+                                    Value::String(s) => s.clone(),
+                                    Value::Null => String::new(),
+                                    other => other.to_string(),
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
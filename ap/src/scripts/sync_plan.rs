@@ -0,0 +1,344 @@
+use std::collections::{ HashMap, HashSet };
+use std::error::Error;
+
+use serde_json::{ Map, Value };
+use sha2::{ Digest, Sha256 };
+
+use crate::lmdb::conflict::DBConflict;
+use crate::lmdb::order::DBOrder;
+use crate::lmdb::row_hash::DBRowHash;
+use crate::lmdb::utils::DB;
+use crate::schema::conflict::SyncConflict;
+use crate::schema::order::{ FieldMeta, FieldOrigin, Order };
+use crate::schema::sync::{ FieldChange, OrderUpdate, SkippedRow, SyncPlan };
+use crate::scripts::conflicts::{
+    conflict,
+    conflict_id,
+    field_values,
+    fields_differ,
+    reconcile_field,
+    with_fields,
+    FieldDecision,
+};
+
+/// Actor recorded in the audit history for changes made by a sync.
+pub const SYNC_ACTOR: &str = "sync";
+
+/// In-memory copy of the `orders` database that a sync run edits.
+///
+/// Importers only touch the working set; nothing reaches LMDB until
+/// [`WorkingSet::commit`], which a dry run never calls.
+pub struct WorkingSet {
+    original: HashMap<String, Order>,
+    orders: HashMap<String, Order>,
+    skipped: Vec<SkippedRow>,
+    /// Row hashes from the previous run, and the ones seen during this one
+    /// with the order each row imported into.
+    row_hashes: HashMap<String, String>,
+    seen_hashes: HashMap<String, (String, String)>,
+    /// Conflicts open before this run, the ones this run queued or refreshed,
+    /// and the open ones whose sides agree again.
+    open_conflicts: HashMap<String, SyncConflict>,
+    detected: HashMap<String, SyncConflict>,
+    cleared: HashSet<String>,
+}
+
+impl WorkingSet {
+    pub fn load(db: &DB) -> Result<Self, Box<dyn Error>> {
+        let original: HashMap<String, Order> = db
+            .get()
+            .map_err(|e| format!("Failed to retrieve orders: {}", e))?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|order| (order.id.clone(), order))
+            .collect();
+        let row_hashes = db.row_hashes().map_err(|e| format!("Failed to retrieve row hashes: {}", e))?;
+        let open_conflicts = db
+            .list_conflicts()
+            .map_err(|e| format!("Failed to retrieve sync conflicts: {}", e))?
+            .into_iter()
+            .map(|conflict| (conflict.id.clone(), conflict))
+            .collect();
+
+        Ok(Self {
+            orders: original.clone(),
+            original,
+            skipped: Vec::new(),
+            row_hashes,
+            seen_hashes: HashMap::new(),
+            open_conflicts,
+            detected: HashMap::new(),
+            cleared: HashSet::new(),
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Order> {
+        self.orders.get(id)
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.orders.values()
+    }
+
+    pub fn upsert(&mut self, order: Order) {
+        self.orders.insert(order.id.clone(), order);
+    }
+
+    /// Whether `key`'s row has the same content as when it was last imported.
+    pub fn row_unchanged(&self, key: &str, hash: &str) -> bool {
+        self.row_hashes.get(key).map(String::as_str) == Some(hash)
+    }
+
+    /// Records the hash of the row `key`, which imported into `order_id`.
+    pub fn record_row_hash(&mut self, order_id: &str, key: String, hash: String) {
+        self.seen_hashes.insert(key, (order_id.to_string(), hash));
+    }
+
+    /// Applies sheet `values` to `order` field by field, recording provenance.
+    /// Fields edited through the API since the last sync, or with a conflict
+    /// already open, keep the order's value and are queued as conflicts for
+    /// `target`. Returns how many fields are in conflict.
+    pub fn apply_sheet_values(
+        &mut self,
+        order: &mut Order,
+        target: &str,
+        values: Vec<(&str, Value)>,
+        now: &str
+    ) -> Result<usize, serde_json::Error> {
+        let current = field_values(order);
+        let mut taken = Map::new();
+        let mut conflicts = 0;
+
+        for (field, sheet) in values {
+            let id = conflict_id(&order.id, field);
+            let value = current.get(field).cloned().unwrap_or(Value::Null);
+            let open = self.detected
+                .get(&id)
+                .or_else(|| self.open_conflicts.get(&id).filter(|_| !self.cleared.contains(&id)))
+                .cloned();
+            let decision = match &open {
+                // The sheet caught up with the API, so the conflict resolved itself
+                Some(_) if sheet == value => FieldDecision::MarkSynced,
+                Some(_) => FieldDecision::Conflict { base: None },
+                None => reconcile_field(order.field_meta.get(field), &value, &sheet),
+            };
+
+            match decision {
+                FieldDecision::Keep => {}
+                FieldDecision::MarkSynced => {
+                    self.detected.remove(&id);
+                    if self.open_conflicts.contains_key(&id) {
+                        self.cleared.insert(id);
+                    }
+                    order.field_meta
+                        .entry(field.to_string())
+                        .or_insert_with(|| FieldMeta {
+                            origin: FieldOrigin::Sheet,
+                            modified_at: now.to_string(),
+                            synced: None,
+                        }).synced = Some(sheet.to_string());
+                }
+                FieldDecision::Take => {
+                    order.field_meta.insert(field.to_string(), FieldMeta {
+                        origin: FieldOrigin::Sheet,
+                        modified_at: now.to_string(),
+                        synced: Some(sheet.to_string()),
+                    });
+                    taken.insert(field.to_string(), sheet);
+                }
+                FieldDecision::Conflict { base } => {
+                    tracing::warn!("order {} field {} was edited in the sheet and through the API", order.id, field);
+                    let conflict = conflict(order, field, target, base, sheet, open.as_ref(), now);
+                    self.cleared.remove(&id);
+                    self.detected.insert(id, conflict);
+                    conflicts += 1;
+                }
+            }
+        }
+
+        *order = with_fields(order, taken)?;
+        Ok(conflicts)
+    }
+
+    pub fn skip(&mut self, source: &str, row: usize, reason: &str) {
+        self.skipped.push(SkippedRow {
+            source: source.to_string(),
+            row,
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Diffs the working set against what was loaded.
+    pub fn plan(&self, dry_run: bool) -> SyncPlan {
+        let mut inserts = Vec::new();
+        let mut updates = Vec::new();
+
+        for (id, order) in &self.orders {
+            match self.original.get(id) {
+                None => inserts.push(order.clone()),
+                Some(before) if fields_differ(before, order) => {
+                    updates.push(OrderUpdate { id: id.clone(), changes: diff_orders(before, order) });
+                }
+                Some(_) => {}
+            }
+        }
+
+        inserts.sort_by(|a, b| a.id.cmp(&b.id));
+        updates.sort_by(|a, b| a.id.cmp(&b.id));
+
+        // Only conflicts that are new or whose values moved since they were queued
+        let mut conflicts: Vec<SyncConflict> = self.detected
+            .values()
+            .filter(|c| self.open_conflicts.get(&c.id) != Some(*c))
+            .cloned()
+            .collect();
+        conflicts.sort_by(|a, b| a.id.cmp(&b.id));
+
+        SyncPlan { dry_run, inserts, updates, skipped: self.skipped.clone(), conflicts }
+    }
+
+    /// Writes every inserted or changed order, the new row hashes and the
+    /// conflict queue in one transaction. An order changed through the API
+    /// since [`WorkingSet::load`] keeps that change: it isn't written, and
+    /// neither are its row hashes and conflicts, so the next run imports its
+    /// rows again. Returns the ids of the orders left alone.
+    pub fn commit(&self, db: &DB) -> Result<HashSet<String>, Box<dyn Error>> {
+        let mut txn = db.env.write_txn()?;
+        let mut stale = HashSet::new();
+
+        for (id, order) in &self.orders {
+            let before = self.original.get(id);
+            if before == Some(order) {
+                continue;
+            }
+            let stored = db
+                .store_order_if_unchanged(&mut txn, before.map(|b| b.revision), &mut order.clone(), SYNC_ACTOR)
+                .map_err(|e| format!("Failed to store order {}: {}", id, e))?;
+            if !stored {
+                tracing::warn!("order {} changed during the sync; leaving it for the next run", id);
+                stale.insert(id.clone());
+            }
+        }
+
+        let changed_hashes: HashMap<String, String> = self.seen_hashes
+            .iter()
+            .filter(|(_, (order_id, _))| !stale.contains(order_id))
+            .filter(|(key, (_, hash))| self.row_hashes.get(*key) != Some(hash))
+            .map(|(key, (_, hash))| (key.clone(), hash.clone()))
+            .collect();
+        db.put_row_hashes_in(&mut txn, &changed_hashes).map_err(|e| format!("Failed to store row hashes: {}", e))?;
+
+        let detected: Vec<SyncConflict> = self.detected
+            .values()
+            .filter(|c| !stale.contains(&c.order_id))
+            .cloned()
+            .collect();
+        let cleared: Vec<String> = self.cleared
+            .iter()
+            .filter(|id| self.open_conflicts.get(*id).is_none_or(|c| !stale.contains(&c.order_id)))
+            .cloned()
+            .collect();
+        db
+            .update_conflicts_in(&mut txn, &detected, &cleared)
+            .map_err(|e| format!("Failed to store sync conflicts: {}", e))?;
+
+        txn.commit()?;
+        Ok(stale)
+    }
+}
+
+/// SHA-256 of a row's trimmed cells, prefixed with its sheet position when it has one.
+/// Trailing empty cells don't count, since the Sheets API omits them.
+pub fn row_hash(cells: &[String], position: Option<u32>) -> String {
+    let cells: Vec<&str> = cells.iter().map(|c| c.trim()).collect();
+    let len = cells.iter().rposition(|c| !c.is_empty()).map_or(0, |i| i + 1);
+
+    let mut hasher = Sha256::new();
+    if let Some(position) = position {
+        hasher.update(position.to_string());
+    }
+    for cell in &cells[..len] {
+        // Unit separator keeps ["ab", "c"] and ["a", "bc"] apart
+        hasher.update([0x1f]);
+        hasher.update(cell);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Field-level differences between two versions of an order, sorted by field
+/// name. Provenance (`field_meta`) is not listed.
+pub fn diff_orders(before: &Order, after: &Order) -> Vec<FieldChange> {
+    let (Ok(Value::Object(before)), Ok(Value::Object(after))) = (
+        serde_json::to_value(before),
+        serde_json::to_value(after),
+    ) else {
+        return Vec::new();
+    };
+
+    after
+        .iter()
+        .filter(|(field, value)| *field != "field_meta" && before.get(*field) != Some(value))
+        .map(|(field, value)| FieldChange {
+            field: field.clone(),
+            before: before.get(field).cloned().unwrap_or(Value::Null),
+            after: value.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lmdb::{ error::StorageError, utils::init_db };
+
+    #[tokio::test]
+    async fn commit_leaves_orders_changed_meanwhile() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = init_db(dir.path()).await.unwrap();
+        db.insert(Order::sample("1"), "test").unwrap();
+        db.insert(Order::sample("2"), "test").unwrap();
+
+        let mut orders = WorkingSet::load(&db).unwrap();
+        for id in ["1", "2"] {
+            let mut order = orders.get(id).unwrap().clone();
+            order.qty = Some(5);
+            orders.upsert(order);
+            orders.record_row_hash(id, format!("Sheet1:{}", id), "hash".to_string());
+        }
+
+        // An API edit lands while the sync runs
+        db.modify("2", "api", None, |order| {
+            order.qty = Some(9);
+            Ok::<_, StorageError>(())
+        }).unwrap();
+
+        let stale = orders.commit(&db).unwrap();
+        assert_eq!(stale, HashSet::from(["2".to_string()]));
+        assert_eq!(db.get_single("1".to_string()).unwrap().unwrap().qty, Some(5));
+        assert_eq!(db.get_single("2".to_string()).unwrap().unwrap().qty, Some(9));
+
+        let hashes = db.row_hashes().unwrap();
+        assert!(hashes.contains_key("Sheet1:1"));
+        assert!(!hashes.contains_key("Sheet1:2"));
+    }
+
+    fn cells(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn row_hash_ignores_padding_but_not_content() {
+        let hash = row_hash(&cells(&["shopify", "42", "2024-01-01"]), Some(2));
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, row_hash(&cells(&[" shopify ", "42", "2024-01-01", "", " "]), Some(2)));
+
+        assert_ne!(hash, row_hash(&cells(&["shopify", "43", "2024-01-01"]), Some(2)));
+        assert_ne!(hash, row_hash(&cells(&["shopify", "42", "2024-01-01"]), Some(3)));
+        assert_ne!(hash, row_hash(&cells(&["shopify", "42", "2024-01-01"]), None));
+        // Moving text between cells or blanking a middle cell changes the row
+        assert_ne!(row_hash(&cells(&["ab", "c"]), None), row_hash(&cells(&["a", "bc"]), None));
+        assert_ne!(row_hash(&cells(&["a", "", "b"]), None), row_hash(&cells(&["a", "b"]), None));
+
+        assert_eq!(row_hash(&[], None), row_hash(&cells(&["", " "]), None));
+    }
+}
@@ -0,0 +1,81 @@
+use std::error::Error;
+
+use crate::config::columns::ColumnAliases;
+use crate::config::settings::{ Settings, SheetLocation };
+use crate::lmdb::target::DBSyncTarget;
+use crate::lmdb::utils::DB;
+use crate::schema::sync_target::SyncTarget;
+
+/// Id of the target built from the environment settings. Orders created
+/// through the API are written back through it too.
+pub const DEFAULT_TARGET: &str = "default";
+
+/// A sync target with its column table resolved, ready for the importer.
+#[derive(Debug, Clone)]
+pub struct ResolvedTarget {
+    pub id: String,
+    pub sheet1: SheetLocation,
+    pub sheet2: SheetLocation,
+    pub columns: ColumnAliases,
+    pub marketplace: Option<String>,
+}
+
+impl ResolvedTarget {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            id: DEFAULT_TARGET.to_string(),
+            sheet1: settings.sheet1.clone(),
+            sheet2: settings.sheet2.clone(),
+            columns: settings.columns.clone(),
+            marketplace: None,
+        }
+    }
+
+    /// Fails when the target's column overrides don't fit the alias table.
+    pub fn from_target(target: &SyncTarget) -> Result<Self, String> {
+        let location = |sheet_name: &str| SheetLocation {
+            spreadsheet_id: target.spreadsheet_id.clone(),
+            sheet_name: sheet_name.to_string(),
+        };
+        let columns = ColumnAliases::default()
+            .with_overrides(&target.columns)
+            .map_err(|e| format!("sync target {}: {}", target.id, e))?;
+
+        Ok(Self {
+            id: target.id.clone(),
+            sheet1: location(&target.sheet1_name),
+            sheet2: location(&target.sheet2_name),
+            columns,
+            marketplace: target.marketplace.clone(),
+        })
+    }
+
+    /// Whether `source` (an order's tag) belongs to this target. Untagged
+    /// orders were created through the API and count as the default target's.
+    pub fn owns(&self, source: Option<&str>) -> bool {
+        source.unwrap_or(DEFAULT_TARGET) == self.id
+    }
+}
+
+/// The default target followed by every enabled stored target, sorted by id.
+pub fn enabled_targets(settings: &Settings, db: &DB) -> Result<Vec<ResolvedTarget>, Box<dyn Error>> {
+    let mut stored = db.list_targets().map_err(|e| format!("Failed to retrieve sync targets: {}", e))?;
+    stored.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut targets = vec![ResolvedTarget::from_settings(settings)];
+    for target in stored.iter().filter(|t| t.enabled) {
+        targets.push(ResolvedTarget::from_target(target)?);
+    }
+    Ok(targets)
+}
+
+/// Looks a target up by id, enabled or not.
+pub fn find_target(settings: &Settings, db: &DB, id: &str) -> Result<Option<ResolvedTarget>, Box<dyn Error>> {
+    if id == DEFAULT_TARGET {
+        return Ok(Some(ResolvedTarget::from_settings(settings)));
+    }
+    match db.get_target(id).map_err(|e| format!("Failed to retrieve sync target: {}", e))? {
+        Some(target) => Ok(Some(ResolvedTarget::from_target(&target)?)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
+
+use jsonwebtoken::{ Algorithm, EncodingKey, Header };
+use reqwest::Client;
+use secrecy::{ ExposeSecret, SecretString };
+use serde::{ Deserialize, Serialize };
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+use crate::config::settings::ServiceAccountKey;
+
+const SHEETS_SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+
+/// Tokens are refreshed this long before Google says they expire, so a request
+/// started with a cached token never reaches the API with a stale one.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Lifetime requested for the signed JWT assertion (Google's maximum).
+const ASSERTION_LIFETIME_SECS: u64 = 3600;
+
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("failed to sign JWT assertion: {0}")]
+    Signing(#[from] jsonwebtoken::errors::Error),
+
+    #[error("token request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("token endpoint returned {status}: {body}")]
+    Rejected {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    exp: u64,
+    iat: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default = "default_expires_in")]
+    expires_in: u64,
+}
+
+fn default_expires_in() -> u64 {
+    ASSERTION_LIFETIME_SECS
+}
+
+struct CachedToken {
+    access_token: SecretString,
+    expires_at: Instant,
+}
+
+impl CachedToken {
+    fn is_fresh(&self) -> bool {
+        Instant::now() + REFRESH_MARGIN < self.expires_at
+    }
+}
+
+struct Inner {
+    client_email: String,
+    private_key: SecretString,
+    token_uri: String,
+    http: Client,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+/// Shared, cloneable source of Google OAuth access tokens for the service account.
+///
+/// The token is cached until shortly before it expires. Concurrent callers that
+/// find it stale wait on the same refresh instead of each minting their own.
+#[derive(Clone)]
+pub struct TokenProvider {
+    inner: Arc<Inner>,
+}
+
+impl TokenProvider {
+    pub fn new(key: &ServiceAccountKey, http: Client) -> Self {
+        Self::with_token_uri(key, &key.token_uri, http)
+    }
+
+    /// Same as [`TokenProvider::new`] but exchanges assertions at `token_uri`
+    /// instead of the endpoint named in the key file, e.g. a local mock.
+    pub fn with_token_uri(key: &ServiceAccountKey, token_uri: &str, http: Client) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                client_email: key.client_email.clone(),
+                private_key: key.private_key.clone(),
+                token_uri: token_uri.to_string(),
+                http,
+                cached: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Returns a bearer token valid for at least `REFRESH_MARGIN`.
+    pub async fn access_token(&self) -> Result<String, TokenError> {
+        let mut cached = self.inner.cached.lock().await;
+
+        if let Some(token) = cached.as_ref().filter(|t| t.is_fresh()) {
+            return Ok(token.access_token.expose_secret().to_string());
+        }
+
+        let fresh = self.request_token().await?;
+        let access_token = fresh.access_token.expose_secret().to_string();
+        *cached = Some(fresh);
+        Ok(access_token)
+    }
+
+    /// Drops the cached token so the next call mints a new one, e.g. after a 401.
+    pub async fn invalidate(&self) {
+        *self.inner.cached.lock().await = None;
+    }
+
+    async fn request_token(&self) -> Result<CachedToken, TokenError> {
+        let inner = &self.inner;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let claims = Claims {
+            iss: &inner.client_email,
+            scope: SHEETS_SCOPE,
+            aud: &inner.token_uri,
+            exp: now + ASSERTION_LIFETIME_SECS,
+            iat: now,
+        };
+
+        let jwt = jsonwebtoken::encode(
+            &Header::new(Algorithm::RS256),
+            &claims,
+            &EncodingKey::from_rsa_pem(inner.private_key.expose_secret().as_bytes())?
+        )?;
+
+        let requested_at = Instant::now();
+        let response = inner.http
+            .post(&inner.token_uri)
+            .form(
+                &[
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                    ("assertion", &jwt),
+                ]
+            )
+            .send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(TokenError::Rejected { status, body });
+        }
+
+        let token = response.json::<TokenResponse>().await?;
+        tracing::debug!("Refreshed Google access token, expires in {}s", token.expires_in);
+
+        Ok(CachedToken {
+            access_token: SecretString::from(token.access_token),
+            expires_at: requested_at + Duration::from_secs(token.expires_in),
+        })
+    }
+}
@@ -1,92 +1,259 @@
 use std::collections::HashMap;
+use std::fmt;
+
+use crate::config::columns::{ self, ColumnSpec };
 use crate::schema::order::Order;
 
-pub fn get_sheet1_column_map() -> HashMap<&'static str, usize> {
-    let mut map = HashMap::new();
-    map.insert("MARTKETPLACE", 1);
-    map.insert("BIN_RACK", 4);
-    map.insert("ORDER_ID", 5);
-    map.insert("RETURN_REAS", 6);
-    map.insert("REFUND_YES", 7);
-    map.insert("DATE", 8);
-    map.insert("REFUNDED", 9);
-    map.insert("STOCK_ADDED", 10);
-    map.insert("REFUND_DATE", 11);
-    map.insert("MATCH_TYPE", 12);
-    map.insert("FRASER_CLASSIFICATION", 13);
-    map
+/// Where each logical field sits in a tab, read from its header row.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    columns: HashMap<&'static str, usize>,
+}
+
+/// Header row problems found while resolving a [`HeaderMap`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderError {
+    pub sheet: String,
+    /// Required fields with no matching header, with the aliases that were tried.
+    pub missing: Vec<(String, Vec<String>)>,
+    /// Fields whose aliases matched more than one header, with the matched cells (A1 column, text).
+    pub duplicates: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} header row doesn't match the column mapping:", self.sheet)?;
+        for (field, aliases) in &self.missing {
+            write!(f, " required column {} is missing (looked for {:?});", field, aliases)?;
+        }
+        for (field, cells) in &self.duplicates {
+            let cells: Vec<String> = cells
+                .iter()
+                .map(|(col, text)| format!("{} {:?}", col, text))
+                .collect();
+            write!(f, " column {} appears more than once ({});", field, cells.join(", "))?;
+        }
+        Ok(())
+    }
 }
 
-pub fn get_sheet2_column_map() -> HashMap<&'static str, usize> {
-    let mut map = HashMap::new();
-    map.insert("RETURN_ORDER", 0);
-    map.insert("SHOPIFY_ID", 1);
-    map.insert("MAKRETPLACE", 2);
-    map.insert("RETURNED_SKU", 3);
-    map.insert("OFFER_SKU", 4);
-    map.insert("MATCHED_SKU", 5);
-    map.insert("MATCH_TYPE", 6);
-    map.insert("ROW_NUMBER", 7);
-    map.insert("MANUAL_CONFIRMAT", 8);
-    map.insert("STATUS", 9);
-    map.insert("MARKETPLACE", 10);
-    map.insert("QTY", 11);
-    map.insert("MAINUPDATED", 12);
-    map
+impl std::error::Error for HeaderError {}
+
+/// Header comparison ignores case, spaces and punctuation ("Order ID" == "ORDER_ID").
+fn normalize_header(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
 }
 
+impl HeaderMap {
+    /// Matches the header row against the alias table, failing on any missing
+    /// required field or any field found in more than one column.
+    pub fn resolve(sheet: &str, header: &[String], specs: &[ColumnSpec]) -> Result<Self, HeaderError> {
+        let normalized: Vec<String> = header
+            .iter()
+            .map(|h| normalize_header(h))
+            .collect();
+
+        let mut columns = HashMap::new();
+        let mut missing = Vec::new();
+        let mut duplicates = Vec::new();
 
-pub fn update_rows_for_order(
-    order: &Order,
-    existing_row_sheet1: &mut Vec<String>,
-    existing_row_sheet2: &mut Vec<String>,
-) {
-    let sheet1_map = get_sheet1_column_map();
-    let sheet2_map = get_sheet2_column_map();
+        for spec in specs {
+            let aliases: Vec<String> = spec.aliases
+                .iter()
+                .map(|a| normalize_header(a))
+                .collect();
+            let matches: Vec<usize> = normalized
+                .iter()
+                .enumerate()
+                .filter(|(_, h)| !h.is_empty() && aliases.contains(h))
+                .map(|(idx, _)| idx)
+                .collect();
 
-    let mut update_cell = |row: &mut Vec<String>, map: &HashMap<&str, usize>, key: &str, value: &str| {
-        if let Some(&idx) = map.get(key) {
-            if idx >= row.len() {
-                row.resize(idx + 1, "".to_string());
+            match matches.as_slice() {
+                [] if spec.required => missing.push((spec.field.to_string(), spec.aliases.clone())),
+                [] => {}
+                [idx] => {
+                    columns.insert(spec.field, *idx);
+                }
+                _ =>
+                    duplicates.push((
+                        spec.field.to_string(),
+                        matches
+                            .iter()
+                            .map(|&idx| (column_letter(idx), header[idx].clone()))
+                            .collect(),
+                    )),
             }
-            row[idx] = value.to_string();
         }
-    };
 
-    
-    // marketplace is mandatory String, update both sheets (note: sheet1 key typo "MARTKETPLACE")
-    update_cell(existing_row_sheet1, &sheet1_map, "MARTKETPLACE", &order.marketplace);
-    update_cell(existing_row_sheet2, &sheet2_map, "MARKETPLACE", &order.marketplace);
+        if missing.is_empty() && duplicates.is_empty() {
+            Ok(Self { columns })
+        } else {
+            Err(HeaderError { sheet: sheet.to_string(), missing, duplicates })
+        }
+    }
+
+    pub fn index(&self, field: &str) -> Option<usize> {
+        self.columns.get(field).copied()
+    }
+
+    /// The trimmed, non-empty value of `field` in `row`.
+    pub fn cell<'a>(&self, row: &'a [String], field: &str) -> Option<&'a str> {
+        self.index(field)
+            .and_then(|idx| row.get(idx))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+    }
+}
+
+/// 0-based column index to its A1 letter(s).
+pub fn column_letter(mut idx: usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push((b'A' + (idx % 26) as u8) as char);
+        if idx < 26 {
+            break;
+        }
+        idx = idx / 26 - 1;
+    }
+    letters.iter().rev().collect()
+}
+
+/// The Sheet1 columns an order's fields are written back to, by logical
+/// field. ORDER_ID locates the row and is never written; fields that are
+/// `None` leave their cell alone. DATE isn't written: the import doesn't read
+/// it, and `order.date` is the day the order was imported.
+pub fn sheet1_fields(order: &Order) -> Vec<(&'static str, String)> {
+    let mut fields = vec![(columns::MARKETPLACE, order.marketplace.clone())];
+    if let Some(match_type) = order.match_type {
+        fields.push((columns::MATCH_TYPE, match_type.as_str().to_string()));
+    }
+    fields
+}
+
+/// The Sheet2 cells an order's fields are written back to, mirroring the
+/// Sheet2 import. ROW_NUMBER points at the Sheet1 row and is left to the sync.
+pub fn sheet2_fields(order: &Order) -> Vec<(&'static str, String)> {
+    let optional = [
+        (columns::RETURN_ORDER, order.return_order.map(|v| v.to_string())),
+        (columns::SHOPIFY_ID, order.shopify_id.clone()),
+        (columns::RETURNED_SKU, order.returned_sku.clone()),
+        (columns::OFFER_SKU, order.offer_sku.clone()),
+        (columns::MATCHED_SKU, order.matched_sku.clone()),
+        (columns::MATCH_TYPE, order.match_type.map(|v| v.as_str().to_string())),
+        (columns::MANUAL_CONFIRMATION, order.manual_confirmation.map(|v| v.as_str().to_string())),
+        (columns::STATUS, order.status.map(|v| v.as_str().to_string())),
+        (columns::QTY, order.qty.map(|v| v.to_string())),
+        (columns::MAIN_UPDATED, order.main_updated.clone()),
+    ];
+    let mut fields = vec![(columns::MARKETPLACE, order.marketplace.clone())];
+    fields.extend(optional.into_iter().filter_map(|(field, value)| Some((field, value?))));
+    fields
+}
+
+/// The cells of `row` whose trimmed text differs from the value `fields` give
+/// them, as 0-based column and new text. Fields the tab has no column for are skipped.
+pub fn changed_cells(
+    row: &[String],
+    headers: &HeaderMap,
+    fields: Vec<(&'static str, String)>
+) -> Vec<(usize, String)> {
+    fields
+        .into_iter()
+        .filter_map(|(field, value)| {
+            let idx = headers.index(field)?;
+            let current = row.get(idx).map_or("", |c| c.trim());
+            (current != value).then_some((idx, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::columns::{ ColumnAliases, ColumnOverrides };
+    use crate::schema::order::{ MatchType, ReturnStatus };
 
-    if let Some(return_order) = order.return_order {
-        let val_str = return_order.to_string();
-        update_cell(existing_row_sheet1, &sheet1_map, "RETURN_REAS", &val_str);
-        update_cell(existing_row_sheet2, &sheet2_map, "RETURN_ORDER", &val_str);
+    fn row(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|c| c.to_string()).collect()
     }
 
-    if let Some(ref manual_confirmation) = order.manual_confirmation {
-        update_cell(existing_row_sheet1, &sheet1_map, "REFUND_YES", manual_confirmation);
-        update_cell(existing_row_sheet2, &sheet2_map, "MANUAL_CONFIRMAT", manual_confirmation);
+    #[test]
+    fn headers_match_by_alias_in_any_order() {
+        let aliases = ColumnAliases::default();
+        let header = row(&["Order ID", "", "martketplace", "Notes", "return reas"]);
+        let headers = HeaderMap::resolve("Sheet1", &header, &aliases.sheet1).unwrap();
+
+        assert_eq!(headers.index(columns::ORDER_ID), Some(0));
+        assert_eq!(headers.index(columns::MARKETPLACE), Some(2));
+        assert_eq!(headers.index(columns::RETURN_REASON), Some(4));
+        assert_eq!(headers.index(columns::DATE), None);
+
+        let values = row(&[" 42 ", "x", "  "]);
+        assert_eq!(headers.cell(&values, columns::ORDER_ID), Some("42"));
+        assert_eq!(headers.cell(&values, columns::MARKETPLACE), None);
+        assert_eq!(headers.cell(&values, columns::RETURN_REASON), None);
     }
 
-    if let Some(ref status) = order.status {
-        update_cell(existing_row_sheet1, &sheet1_map, "BIN_RACK", status);
-        update_cell(existing_row_sheet2, &sheet2_map, "STATUS", status);
+    #[test]
+    fn headers_report_missing_and_duplicate_columns() {
+        let aliases = ColumnAliases::default();
+        let header = row(&["MARKETPLACE", "DATE", "MARTKETPLACE"]);
+        let err = HeaderMap::resolve("Sheet1", &header, &aliases.sheet1).unwrap_err();
+
+        assert_eq!(err.sheet, "Sheet1");
+        assert_eq!(err.missing, [(columns::ORDER_ID.to_string(), vec![columns::ORDER_ID.to_string()])]);
+        assert_eq!(err.duplicates, [(
+            columns::MARKETPLACE.to_string(),
+            vec![("A".to_string(), "MARKETPLACE".to_string()), ("C".to_string(), "MARTKETPLACE".to_string())],
+        )]);
     }
 
-    if let Some(qty) = order.qty {
-        let val_str = qty.to_string();
-        update_cell(existing_row_sheet1, &sheet1_map, "STOCK_ADDED", &val_str);
-        update_cell(existing_row_sheet2, &sheet2_map, "QTY", &val_str);
+    #[test]
+    fn headers_follow_alias_overrides() {
+        let mut overrides = ColumnOverrides::default();
+        overrides.sheet2.insert("return_order".to_string(), vec!["Return #".to_string()]);
+        let aliases = ColumnAliases::default().with_overrides(&overrides).unwrap();
+
+        let headers = HeaderMap::resolve("Returns", &row(&["QUANTITY", "return"]), &aliases.sheet2).unwrap();
+        assert_eq!((headers.index(columns::RETURN_ORDER), headers.index(columns::QTY)), (Some(1), Some(0)));
+        // The default alias no longer matches
+        assert!(HeaderMap::resolve("Returns", &row(&["RETURN_ORDER"]), &aliases.sheet2).is_err());
+
+        overrides.sheet2.insert("NOT_A_FIELD".to_string(), vec!["x".to_string()]);
+        assert!(ColumnAliases::default().with_overrides(&overrides).is_err());
     }
 
-    if let Some(ref main_updated) = order.main_updated {
-        update_cell(existing_row_sheet1, &sheet1_map, "REFUND_DATE", main_updated);
-        update_cell(existing_row_sheet2, &sheet2_map, "MAINUPDATED", main_updated);
+    #[test]
+    fn column_letters() {
+        let letters: Vec<String> = [0, 25, 26, 51, 52, 701, 702].into_iter().map(column_letter).collect();
+        assert_eq!(letters, ["A", "Z", "AA", "AZ", "BA", "ZZ", "AAA"]);
     }
 
-    // Date, created_at, updated_at update only sheet1 because sheet2 doesn't have those columns
-    update_cell(existing_row_sheet1, &sheet1_map, "DATE", &order.date);
-    update_cell(existing_row_sheet1, &sheet1_map, "REFUNDED", &order.created_at);
-    update_cell(existing_row_sheet1, &sheet1_map, "MATCH_TYPE", &order.updated_at);
-}
\ No newline at end of file
+    #[test]
+    fn writes_only_changed_cells_of_each_fields_column() {
+        let aliases = ColumnAliases::default();
+        let sheet1 = row(&["MARTKETPLACE", "BIN_RACK", "ORDER_ID", "RETURN_REAS", "DATE", "REFUNDED", "MATCH_TYPE"]);
+        let headers = HeaderMap::resolve("Sheet1", &sheet1, &aliases.sheet1).unwrap();
+        let order = Order {
+            marketplace: "amazon".to_string(),
+            match_type: Some(MatchType::Manual),
+            status: Some(ReturnStatus::Received),
+            ..Order::sample("1")
+        };
+
+        let current = row(&["shopify", "B-7", "1", "damaged", "2024-01-01", "yes"]);
+        let cells = changed_cells(&current, &headers, sheet1_fields(&order));
+        assert_eq!(cells, [(0, "amazon".to_string()), (6, "manual".to_string())]);
+
+        let sheet2 = row(&["RETURN_ORDER", "STATUS", "MARKETPLACE", "QTY"]);
+        let headers = HeaderMap::resolve("Sheet2", &sheet2, &aliases.sheet2).unwrap();
+        let current = row(&["1", "pending", " amazon "]);
+        let cells = changed_cells(&current, &headers, sheet2_fields(&order));
+        // No return_order or qty on the order, so those cells are left alone
+        assert_eq!(cells, [(1, "received".to_string())]);
+    }
+}
@@ -1,8 +1,12 @@
 use utoipa::OpenApi;
 use crate::schema::application::Application;
+use crate::routes::error::{ FieldError, ProblemDetails };
 
 use crate::routes::application::*;
+use crate::routes::conflict::*;
 use crate::routes::order::*;
+use crate::routes::sync::*;
+use crate::routes::sync_target::*;
 
 #[derive(OpenApi)]
 #[openapi(
@@ -10,16 +14,32 @@ use crate::routes::order::*;
         create_application,
         get_application,
         update_application,
+        patch_application,
         delete_application,
         list_applications,
+        get_application_history,
         insert_order,
         get_order,
         list_orders,
         update_order,
-        delete_order
+        patch_order,
+        delete_order,
+        transition_order,
+        get_order_history,
+        dry_run_sync,
+        sync_status,
+        run_sync,
+        create_target,
+        list_targets,
+        get_target,
+        update_target,
+        delete_target,
+        run_target,
+        list_conflicts,
+        get_conflict,
+        resolve_conflict
 
     ),
-    components(schemas(Application))
+    components(schemas(Application, ProblemDetails, FieldError))
 )]
-
 pub struct ApiDoc;
\ No newline at end of file